@@ -1,2 +1,3 @@
+#!/usr/bin/env bash
 echo "{\"current_time\": \"$(date '+%Y-%m-%d %H:%M:%S')\"}"
 
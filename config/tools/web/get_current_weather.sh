@@ -1 +1,2 @@
+#!/usr/bin/env bash
 curl "wttr.in?format=j1"
\ No newline at end of file
@@ -1,10 +1,29 @@
-use std::{error::Error, fs::{self}};
+use std::{collections::HashMap, error::Error, fs::{self}};
 use chromadb::v2::{collection::CollectionEntries, embeddings::openai::{OpenAIConfig, OpenAIEmbeddings}, ChromaClient, ChromaCollection};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use ragents::common::{cli::CliChromadb, config::{RagsConfig, ToolsConfig}, rag::Rag, tool::Tool};
-use tokenizers::Tokenizer;
-   
+use ragents::{common::{rag::Rag, tool::Tool}, CliChromadb};
+use serde::Deserialize;
+
+/// The `tools` of a config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ToolsConfig {
+    tools: HashMap<String, String>,
+}
+
+/// The `rags` of a config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RagsConfig {
+    rags: HashMap<String, String>,
+}
+
+fn load_yaml<T: for<'a> Deserialize<'a>>(path: &str) -> Result<T> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to load config at '{path}'"))?;
+    serde_yaml::from_str(&content).with_context(|| format!("Invalid config at '{path}'"))
+}
+
 pub async fn rags_load_chromadb(name: &str, rag: Rag) -> Result<(),  Box<dyn Error>> {
     let client: ChromaClient = ChromaClient::new(Default::default());
     let collection: ChromaCollection = client.get_or_create_collection(name, None).await?;
@@ -16,20 +35,20 @@ pub async fn rags_load_chromadb(name: &str, rag: Rag) -> Result<(),  Box<dyn Err
     let mut chunks = Vec::new();
     let mut ids = Vec::new();
     
-    let tokenizer = Tokenizer::from_pretrained("bert-base-cased", None).unwrap();
+    // Chunk sizes count whitespace separated words
+    let step = rag.rag_chunk_size.saturating_sub(rag.rag_chunk_overlap).max(1);
     for document in rag.documents.iter() {
         let content = fs::read_to_string(document)?;
-        let encoding = tokenizer.encode(content, false).unwrap();
-        let tokens: Vec<&str>= encoding.get_tokens().iter().map(|s| s.as_str()).collect();
+        let words: Vec<&str> = content.split_whitespace().collect();
         let mut start = 0;
 
-        while start < tokens.len() {
-            let end = std::cmp::min(start + rag.rag_chunk_size, tokens.len());
-            let chunk = tokens[start..end].join(" ");  // Use slice directly for chunks
+        while start < words.len() {
+            let end = std::cmp::min(start + rag.rag_chunk_size, words.len());
+            let chunk = words[start..end].join(" ");
             
             chunks.push(chunk);
             ids.push(format!("{}{}{}", document, "-id-", start));
-            start += rag.rag_chunk_size - rag.rag_chunk_overlap;
+            start += step;
         }
     }
     println!("{:?}", chunks);
@@ -45,7 +64,7 @@ pub async fn rags_load_chromadb(name: &str, rag: Rag) -> Result<(),  Box<dyn Err
 
     let openai_embeddings = OpenAIEmbeddings::new(OpenAIConfig{ 
         api_endpoint: format!("{}{}", std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| "".to_string()), "/embeddings"),
-        api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()).into(),
+        api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()),
         model: rag.rag_embedding_model,
     });
     collection.upsert(collection_entries, Some(Box::new(openai_embeddings))).await?;
@@ -53,7 +72,11 @@ pub async fn rags_load_chromadb(name: &str, rag: Rag) -> Result<(),  Box<dyn Err
     Ok(())
 }
 
-pub async fn tools_load_chromadb(name: &str, tool: Tool) -> Result<(),  Box<dyn Error>> {
+pub async fn tools_load_chromadb(name: &str, mut tool: Tool) -> Result<(),  Box<dyn Error>> {
+    let embedding_model = tool
+        .tool_embedding_model
+        .clone()
+        .ok_or_else(|| format!("Error: Tool {} has no tool_embedding_model .", name))?;
     let client = ChromaClient::new(Default::default());
     let collection = client.get_or_create_collection(name, None).await?;
 
@@ -81,8 +104,8 @@ pub async fn tools_load_chromadb(name: &str, tool: Tool) -> Result<(),  Box<dyn
 
     let openai_embeddings = OpenAIEmbeddings::new(OpenAIConfig{ 
         api_endpoint: format!("{}{}", std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| "".to_string()), "/embeddings"),
-        api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()).into(),
-        model: tool.tool_embedding_model,
+        api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()),
+        model: embedding_model,
     });
     collection.upsert(collection_entries, Some(Box::new(openai_embeddings))).await?;
     println!("\x1b[32;1mSuccessful!\x1b[0m");
@@ -94,16 +117,16 @@ pub async fn tools_load_chromadb(name: &str, tool: Tool) -> Result<(),  Box<dyn
 async fn main() -> Result<(),  Box<dyn Error>> {
     let cli = CliChromadb::parse();
 
-    let tools_config = ToolsConfig::init(cli.tools.into())?;
-    let rags_config = RagsConfig::init(cli.rags.into())?;
+    let tools_config: ToolsConfig = load_yaml(&cli.tools)?;
+    let rags_config: RagsConfig = load_yaml(&cli.rags)?;
 
     for (name, path) in rags_config.rags.iter() {
-        let rag = Rag::init(name, path).expect(&format!("Error: Rag {} not found .",name));
+        let rag = Rag::init(name, path)?;
         rags_load_chromadb(name, rag).await?;
     }
 
     for (name, path) in tools_config.tools.iter() {
-        let tool = Tool::init(name, path).expect(&format!("Error: Rag {} not found .",name));
+        let tool = Tool::init(name, path)?;
         tools_load_chromadb(name, tool).await?;
     }
     Ok(())
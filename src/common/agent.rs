@@ -1,39 +1,48 @@
-use std::{collections::HashMap, fs::read_to_string};
+use std::{collections::HashMap, env, fs::read_to_string};
 
 use anyhow::{bail, Context, Ok, Result};
-use async_openai::types::ChatCompletionTool;
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionTool};
+use fancy_regex::{Captures, Regex};
 use serde::Deserialize;
 
-use super::{rag::Rag, tool::Tool, RAG_TEMPLATE};
+use super::{error::RagentsError, rag::{rag_template, Rag}, sampling::SamplingParams, tool::Tool, TOOL_TOP_K};
 
-#[derive(Debug, Clone, Deserialize)]
+lazy_static::lazy_static! {
+    static ref RE_VARIABLE: Regex = Regex::new(r"\{\{(\w+)\}\}").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolSelection {
+    /// Send every tool of the agent
+    #[default]
+    All,
+    /// Send only the tools closest to the user message
+    Semantic,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(default)]
 pub struct Agent{
     pub name: String,
     pub description: String,
     pub instructions: String,
+    /// Overrides the global model, as `model` or `provider:model`
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub sampling: SamplingParams,
     pub tools: Vec<String>,
-    pub rags: Option<String>,
+    pub tool_selection: ToolSelection,
+    pub tool_top_k: Option<usize>,
+    pub rags: Vec<String>,
+    pub max_steps: Option<usize>,
+    pub variables: HashMap<String, String>,
     #[serde(skip)]
-    pub rag: Rag,
+    pub rag: Vec<Rag>,
     #[serde(skip)]
     pub tool: Vec<Tool>,
 }
 
-impl Default for Agent  {
-    fn default() -> Self {
-        Self {
-            name: Default::default(),
-            description: Default::default(),
-            instructions: Default::default(),
-            tools: Default::default(),
-            rags: Default::default(),
-            rag: Default::default(),
-            tool: Default::default(),
-        }
-    }
-}
-
 impl Agent {
     pub fn init(agent_name: &str, agent_path: &str) -> Result<Self>  {
         let err = || format!("Failed to load config at '{}'", agent_name);
@@ -52,7 +61,7 @@ impl Agent {
                     vec_tool.push(tool);
                 },
                 None => {
-                    bail!("There is no tool found.");
+                    bail!(RagentsError::Config(format!("Agent '{}' uses unknown tool '{tool_name}'", self.name)));
                 }
             }
         }
@@ -61,20 +70,37 @@ impl Agent {
     }
 
     pub fn rag(&mut self, rags: &HashMap<String, String>) -> Result<()> {
-        if let Some(rag_name) = &self.rags {
+        let mut vec_rag: Vec<Rag> = vec![];
+        for rag_name in self.rags.iter() {
             match rags.get(rag_name) {
                 Some(rag_path) => {
                     let rag = Rag::init(rag_name, rag_path)?;
-                    self.rag = rag;
+                    vec_rag.push(rag);
                 },
                 None => {
-                    bail!("There is no rag found.");
+                    bail!(RagentsError::Config(format!("Agent '{}' uses unknown rag '{rag_name}'", self.name)));
                 }
             }
         }
+        self.rag = vec_rag;
         Ok(())
     }
 
+    pub fn interpolated_instructions(&self) -> String {
+        interpolate_variables(&self.instructions, &self.variables)
+    }
+
+    pub fn echo_system_message(&self) -> Result<Option<ChatCompletionRequestMessage>> {
+        let instructions = self.interpolated_instructions();
+        if instructions.trim().is_empty() {
+            return Ok(None);
+        }
+        let message = ChatCompletionRequestSystemMessageArgs::default()
+            .content(instructions)
+            .build()?;
+        Ok(Some(message.into()))
+    }
+
     pub fn echo_tool(&self) -> Result<Vec<ChatCompletionTool>> {
         let mut tools:Vec<ChatCompletionTool> = vec![];
         for tool in self.tool.iter() {
@@ -83,23 +109,54 @@ impl Agent {
         Ok(tools)
     }
 
+    pub fn tool_top_k(&self) -> usize {
+        self.tool_top_k.unwrap_or(TOOL_TOP_K)
+    }
+
     pub fn tool_exec(&self, name: &str) -> Option<&str> {
-        for tool in self.tool.iter() {
-            if let Some(tool_exec) = tool.tool_exec(name) {
-                return Some(tool_exec);
-            }
-        }
-        None
+        self.find_tool(name).and_then(|tool| tool.tool_exec(name))
+    }
+
+    pub fn find_tool(&self, name: &str) -> Option<&Tool> {
+        self.tool.iter().find(|tool| tool.tool_exec(name).is_some())
     }
 
     pub fn rag_template(&self, embeddings: &str, text: &str) -> String {
-        if embeddings.is_empty() {
-            return text.to_string();
-        }
-        RAG_TEMPLATE
-            // .as_ref()
-            // .unwrap_or(RAG_TEMPLATE)
-            .replace("__CONTEXT__", embeddings)
-            .replace("__INPUT__", text)
+        rag_template(embeddings, text)
+    }
+}
+
+pub fn interpolate_variables(text: &str, variables: &HashMap<String, String>) -> String {
+    RE_VARIABLE
+        .replace_all(text, |caps: &Captures| {
+            let key = &caps[1];
+            match key {
+                "__os__" => env::consts::OS.to_string(),
+                "__arch__" => env::consts::ARCH.to_string(),
+                "__cwd__" => env::current_dir()
+                    .map(|v| v.display().to_string())
+                    .unwrap_or_default(),
+                "__now__" => chrono::Local::now().to_rfc3339(),
+                _ => variables
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string()),
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_variables() {
+        let variables = HashMap::from([("lang".to_string(), "rust".to_string())]);
+        assert_eq!(
+            interpolate_variables("{{lang}} on {{__os__}}", &variables),
+            format!("rust on {}", env::consts::OS)
+        );
+        assert_eq!(interpolate_variables("{{unknown}}", &variables), "{{unknown}}");
     }
-}
\ No newline at end of file
+}
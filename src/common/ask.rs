@@ -1,220 +1,521 @@
-use std::io::{stdout, Write};
-use std::process::Command;
-use std::sync::Arc;
-use std::collections::HashMap;
-use serde_json::Value;
-use tokio::sync::Mutex;
-
-use anyhow::{Context, Result};
-use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs, ChatCompletionToolType, CreateChatCompletionRequest, CreateChatCompletionRequestArgs, FinishReason, FunctionCall};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use serde_json::{json, Value};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionStreamOptions, ChatCompletionTool, ChatCompletionToolType, CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionRequestArgs, FunctionCall};
 use futures::StreamExt;
+use inquire::Confirm;
+use tokio::{io::{AsyncRead, AsyncReadExt}, process::{Child, Command}, time::timeout};
 
-use crate::common::{config::Config, input::Input};
+use crate::common::{agent::ToolSelection, config::Config, error::{ErrorResponse, RagentsError}, event::{AskEvent, EventSink}, input::Input, tokens::sum_usages, tool::{check_path_args, search_tools, ToolRlimits, ToolSandbox}, WorkingMode, ALLOW_TOOLS_HEADER, AUTONAME_PROMPT, SUMMARIZE_PROMPT, TOOL_STDERR_MAX};
 
-// #[async_recursion::async_recursion]
+/// Ask the model and return the answer, reporting progress to `sink`
 pub async fn ask(
     config: &Config,
     input: Input,
-) -> Result<()> {
+    sink: &dyn EventSink,
+) -> Result<String> {
+    match ask_steps(config, input, sink).await {
+        Ok(output) => {
+            sink.emit(AskEvent::Done)?;
+            Ok(output)
+        }
+        Err(err) => {
+            sink.emit(AskEvent::Error(ErrorResponse::new(&err)))?;
+            Err(err)
+        }
+    }
+}
+
+async fn ask_steps(
+    config: &Config,
+    mut input: Input,
+    sink: &dyn EventSink,
+) -> Result<String> {
     if input.is_empty() {
-        return Ok(());
+        return Ok(String::new());
+    }
+    if let Err(err) = input.use_rag(config).await {
+        log::warn!("{err:#}, answering without RAG context");
+    }
+
+    let mut messages = config.write().echo_message(&input)?;
+    let tools = select_tools(config, &input).await?;
+    let max_steps = config.read().max_steps();
+
+    config.write().before_chat_completion(&input)?;
+
+    let turn_start = messages.len();
+    let mut contents = String::new();
+    let mut usages = vec![];
+    let mut step = 0;
+    loop {
+        let request = build_request(config, &input, &messages, &tools)?;
+        let (output, tool_calls, usage) = chat_completion_streaming(config, request, sink).await?;
+        contents.push_str(&output);
+        usages.extend(usage);
+
+        if tool_calls.is_empty() {
+            break;
+        }
+        step += 1;
+        if step > max_steps {
+            bail!(RagentsError::Tool(format!("Exceeded the maximum of {max_steps} tool-calling steps")));
+        }
+
+        let mut assistant_message = ChatCompletionRequestAssistantMessageArgs::default();
+        if !output.is_empty() {
+            assistant_message.content(output);
+        }
+        messages.push(assistant_message.tool_calls(tool_calls.clone()).build()?.into());
+
+        for (tool_call, response) in run_tool_calls(config, &input, tool_calls, sink).await? {
+            messages.push(
+                ChatCompletionRequestToolMessageArgs::default()
+                    .content(response.to_string())
+                    .tool_call_id(tool_call.id)
+                    .build()?
+                    .into(),
+            );
+        }
+    }
+
+    if let Some(usage) = sum_usages(&usages) {
+        sink.emit(AskEvent::Usage(usage))?;
+    }
+    if !input.rag_sources().is_empty() {
+        sink.emit(AskEvent::Sources(input.rag_sources().to_vec()))?;
+    }
+
+    let output = format!("{}{contents}", input.continue_output().unwrap_or_default());
+    config.write().after_chat_completion(&input, &messages[turn_start..], &output, &usages)?;
+    if let Err(err) = autoname_session(config).await {
+        log::warn!("Failed to name the session: {err:#}");
+    }
+    if let Err(err) = compress_session(config).await {
+        log::warn!("Failed to compress the session: {err:#}");
     }
+    Ok(contents)
+}
 
-    let request_message = config.write().echo_message(&input)?;
-    let request_tool = config.write().echo_tool()?;
+/// Title a temporary session after its first exchange
+async fn autoname_session(config: &Config) -> Result<()> {
+    let messages = match config.write().session_to_autoname() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let title = chat_completion_once(config, messages, AUTONAME_PROMPT).await?;
+    config.write().set_session_autoname(&title);
+    Ok(())
+}
 
-    let request: CreateChatCompletionRequest;
-    if config.read().working_mode.is_realtime() {
-        request = CreateChatCompletionRequestArgs::default()
-        .model(config.read().model.clone())
-        .messages(request_message)
-        .tools(request_tool)
+/// Replace the session's older messages by a summary once it went over `compress_threshold`
+async fn compress_session(config: &Config) -> Result<()> {
+    let messages = match config.read().session_to_compress() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let count = messages.len();
+    let summary = chat_completion_once(config, messages, SUMMARIZE_PROMPT).await?;
+    config.write().compress_session(&summary, count)
+}
+
+/// Ask `prompt` about `messages` without streaming, for the session's own housekeeping
+async fn chat_completion_once(
+    config: &Config,
+    mut messages: Vec<ChatCompletionRequestMessage>,
+    prompt: &str,
+) -> Result<String> {
+    messages.push(
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(prompt)
+            .build()?
+            .into(),
+    );
+    let model = config.read().current_model();
+    let (client, model) = config.read().resolve_model(&model)?;
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(model)
+        .messages(messages)
         .build()?;
-    } else {
-        request = input.request.clone();
+    let response = client.chat().create(request).await?;
+    let content = response
+        .choices
+        .into_iter()
+        .find_map(|choice| choice.message.content)
+        .unwrap_or_default();
+    if content.trim().is_empty() {
+        bail!("The model returned an empty answer");
     }
+    Ok(content)
+}
 
-    let mut stream = config.read().client.chat().create_stream(request).await?;
+async fn select_tools(config: &Config, input: &Input) -> Result<Vec<ChatCompletionTool>> {
+    let (tools, top_k) = match &config.read().agent {
+        Some(agent) if agent.tool_selection == ToolSelection::Semantic => {
+            (agent.tool.clone(), agent.tool_top_k())
+        }
+        _ => return config.read().echo_tool(),
+    };
+    or_all_tools(search_tools(&tools, &input.text, top_k).await, || config.read().echo_tool())
+}
 
-    let mut lock = stdout().lock();
-    let mut contents:String = String::new();
+/// Fall back to every tool when the semantic search failed
+fn or_all_tools(
+    selected: Result<Vec<ChatCompletionTool>>,
+    all_tools: impl FnOnce() -> Result<Vec<ChatCompletionTool>>,
+) -> Result<Vec<ChatCompletionTool>> {
+    match selected {
+        Ok(selected) => Ok(selected),
+        Err(err) => {
+            log::warn!("{err:#}, sending all tools");
+            all_tools()
+        }
+    }
+}
 
-    config.write().before_chat_completion(&input)?;
+fn build_request(
+    config: &Config,
+    input: &Input,
+    messages: &[ChatCompletionRequestMessage],
+    tools: &[ChatCompletionTool],
+) -> Result<CreateChatCompletionRequest> {
+    if input.is_web() {
+        let mut request = input.request.clone();
+        request.messages = messages.to_vec();
+        request.stream = Some(true);
+        request.stream_options = Some(ChatCompletionStreamOptions { include_usage: true });
+        if !tools.is_empty() {
+            request.tools.get_or_insert_with(Vec::new).extend(tools.iter().cloned());
+        }
+        config.read().sampling().apply(&mut request);
+        return Ok(request);
+    }
+    let mut request = CreateChatCompletionRequestArgs::default();
+    request
+        .model(config.read().current_model())
+        .messages(messages.to_vec())
+        .stream_options(ChatCompletionStreamOptions { include_usage: true });
+    if !tools.is_empty() {
+        request.tools(tools.to_vec());
+    }
+    let mut request = request.build()?;
+    config.read().sampling().apply(&mut request);
+    Ok(request)
+}
 
+async fn chat_completion_streaming(
+    config: &Config,
+    mut request: CreateChatCompletionRequest,
+    sink: &dyn EventSink,
+) -> Result<(String, Vec<ChatCompletionMessageToolCall>, Option<CompletionUsage>)> {
+    let (client, model) = config.read().resolve_model(&request.model)?;
+    request.model = model;
+    let mut stream = client.chat().create_stream(request).await?;
 
-    let tool_call_states: Arc<Mutex<HashMap<(u32, u32), ChatCompletionMessageToolCall>>> =
-    Arc::new(Mutex::new(HashMap::new()));
+    let mut contents = String::new();
+    let mut tool_call_states: BTreeMap<(u32, u32), ChatCompletionMessageToolCall> = BTreeMap::new();
+    let mut last_usage = None;
 
     while let Some(result) = stream.next().await {
-        match result {
-            Ok(response) => {
-                println!("{:?}", response);
-                for chat_choice in response.choices {
-                    let function_responses: Arc<
-                        Mutex<Vec<(ChatCompletionMessageToolCall, Value)>>,
-                    > = Arc::new(Mutex::new(Vec::new()));
-
-                    if let Some(tool_calls) = chat_choice.delta.tool_calls {
-                        for tool_call_chunk in tool_calls.into_iter() {
-                            let key = (chat_choice.index, tool_call_chunk.index);
-                            let states = tool_call_states.clone();
-                            let tool_call_data = tool_call_chunk.clone();
-
-                            let mut states_lock = states.lock().await;
-                            let state = states_lock.entry(key).or_insert_with(|| {
-                                ChatCompletionMessageToolCall {
-                                    id: tool_call_data.id.clone().unwrap_or_default(),
-                                    r#type: ChatCompletionToolType::Function,
-                                    function: FunctionCall {
-                                        name: tool_call_data
-                                            .function
-                                            .as_ref()
-                                            .and_then(|f| f.name.clone())
-                                            .unwrap_or_default(),
-                                        arguments: tool_call_data
-                                            .function
-                                            .as_ref()
-                                            .and_then(|f| f.arguments.clone())
-                                            .unwrap_or_default(),
-                                    },
-                                }
-                            });
-                            if let Some(arguments) = tool_call_chunk
-                                .function
-                                .as_ref()
-                                .and_then(|f| f.arguments.as_ref())
-                            {
-                                state.function.arguments.push_str(arguments);
-                            }
+        let response = result?;
+        if let Some(usage) = response.usage {
+            last_usage = Some(usage);
+        }
+        for chat_choice in response.choices {
+            if let Some(tool_calls) = chat_choice.delta.tool_calls {
+                for tool_call_chunk in tool_calls.into_iter() {
+                    let key = (chat_choice.index, tool_call_chunk.index);
+                    let state = tool_call_states.entry(key).or_insert_with(|| {
+                        ChatCompletionMessageToolCall {
+                            id: tool_call_chunk.id.clone().unwrap_or_default(),
+                            r#type: ChatCompletionToolType::Function,
+                            function: FunctionCall {
+                                name: String::new(),
+                                arguments: String::new(),
+                            },
                         }
-                    }
-
-                    if let Some(finish_reason) = &chat_choice.finish_reason {
-                        if matches!(finish_reason, FinishReason::ToolCalls) {
-                            let tool_call_states_clone = tool_call_states.clone();
-
-                            let tool_calls_to_process = {
-                                let states_lock = tool_call_states_clone.lock().await;
-                                states_lock
-                                    .iter()
-                                    .map(|(_key, tool_call)| {
-                                        let name = tool_call.function.name.clone();
-                                        let args = tool_call.function.arguments.clone();
-                                        let tool_call_clone = tool_call.clone();
-                                        (name, args, tool_call_clone)
-                                    })
-                                    .collect::<Vec<_>>()
-                            };
-
-                            let mut handles = Vec::new();
-                            for (name, args, tool_call_clone) in tool_calls_to_process {
-                                let response_content_clone = function_responses.clone();
-                                // let cmd = config.tool_exec(&name);
-                                let handle = tokio::spawn(async move {
-                                    let response_content = call_fn(&name, &args).await.unwrap();
-                                    let mut function_responses_lock =
-                                        response_content_clone.lock().await;
-                                    function_responses_lock
-                                        .push((tool_call_clone, response_content));
-                                });
-                                handles.push(handle);
-                            }
-
-                            for handle in handles {
-                                handle.await.unwrap();
-                            }
-
-                            let function_responses_clone = function_responses.clone();
-                            let function_responses_lock = function_responses_clone.lock().await;
-                            
-                            let mut messages = config.write().echo_message(&input)?;
-                            
-                            let tool_calls: Vec<ChatCompletionMessageToolCall> =
-                                function_responses_lock
-                                    .iter()
-                                    .map(|tc| tc.0.clone())
-                                    .collect();
-
-                            let assistant_messages: ChatCompletionRequestMessage =
-                                ChatCompletionRequestAssistantMessageArgs::default()
-                                    .tool_calls(tool_calls)
-                                    .build()
-                                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
-                                    .unwrap()
-                                    .into();
-
-                            let tool_messages: Vec<ChatCompletionRequestMessage> =
-                                function_responses_lock
-                                    .iter()
-                                    .map(|tc| {
-                                        ChatCompletionRequestToolMessageArgs::default()
-                                            .content(tc.1.to_string())
-                                            .tool_call_id(tc.0.id.clone())
-                                            .build()
-                                            .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
-                                            .unwrap()
-                                            .into()
-                                    })
-                                    .collect();
-
-                            messages.push(assistant_messages);
-                            messages.extend(tool_messages);
-
-                            let request = CreateChatCompletionRequestArgs::default()
-                                .model(config.read().model.clone())
-                                .messages(messages)
-                                .build()?;
-                            
-                            let mut stream = config.read().client.chat().create_stream(request).await?;
-
-                            let mut response_content = String::new();
-                            let mut lock = stdout().lock();
-                            while let Some(result) = stream.next().await {
-                                match result {
-                                    Ok(response) => {
-                                        for chat_choice in response.choices.iter() {
-                                            if let Some(ref content) = chat_choice.delta.content {
-                                                contents += content;
-                                                write!(lock, "{}", content).unwrap();
-                                                response_content.push_str(content);
-                                            }
-                                        }
-                                    }
-                                    Err(err) => {
-                                        writeln!(lock, "error: {err}").unwrap();
-                                        // return Err(Box::new(err) as Box<dyn std::error::Error>);
-                                    }
-                                }
-                            }
+                    });
+                    if let Some(function) = tool_call_chunk.function {
+                        if let Some(name) = function.name {
+                            state.function.name.push_str(&name);
+                        }
+                        if let Some(arguments) = function.arguments {
+                            state.function.arguments.push_str(&arguments);
                         }
-                    }
-
-                    if let Some(content) = &chat_choice.delta.content {
-                        let mut lock = stdout().lock();
-                        contents += content;
-                        write!(lock, "{}", content).unwrap();
                     }
                 }
             }
-            Err(err) => {
-                writeln!(lock, "error: {err}").unwrap();
+
+            if let Some(content) = chat_choice.delta.content {
+                contents += &content;
+                sink.emit(AskEvent::TextDelta(content))?;
             }
         }
-        stdout().flush()?;
     }
-    config.write().after_chat_completion(&input, &contents)?;
+    Ok((contents, tool_call_states.into_values().collect(), last_usage))
+}
+
+async fn run_tool_calls(
+    config: &Config,
+    input: &Input,
+    tool_calls: Vec<ChatCompletionMessageToolCall>,
+    sink: &dyn EventSink,
+) -> Result<Vec<(ChatCompletionMessageToolCall, Value)>> {
+    let mut handles = Vec::new();
+    for tool_call in tool_calls {
+        let exec = config.read().find_tool(&tool_call.function.name).map(|tool| {
+            let cmd = tool.tool_exec(&tool_call.function.name).unwrap_or_default();
+            (cmd.to_string(), tool.sandbox.clone(), tool.need_confirm(&tool_call.function.name))
+        });
+        let exec = match exec {
+            Some((cmd, sandbox, need_confirm)) => {
+                let working_mode = config.read().working_mode;
+                confirm_tool_call(working_mode, input, &tool_call, need_confirm)
+                    .map(|_| (cmd, sandbox))
+            }
+            None => Err(anyhow!("Unknown tool '{}'", tool_call.function.name)),
+        };
+        sink.emit(AskEvent::ToolCallStarted {
+            id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            arguments: tool_call.function.arguments.clone(),
+        })?;
+        let handle = tokio::spawn(async move {
+            let response = match exec {
+                Ok((cmd, sandbox)) => call_fn(&cmd, &tool_call.function.arguments, &sandbox).await,
+                Err(err) => Err(err),
+            };
+            let response = response.unwrap_or_else(|err| tool_error(&err));
+            (tool_call, response)
+        });
+        handles.push(handle);
+    }
+
+    let mut function_responses = Vec::new();
+    for handle in handles {
+        let (tool_call, response) = handle.await?;
+        sink.emit(AskEvent::ToolCallFinished {
+            id: tool_call.id.clone(),
+            name: tool_call.function.name.clone(),
+            result: response.clone(),
+        })?;
+        function_responses.push((tool_call, response));
+    }
+    Ok(function_responses)
+}
+
+fn confirm_tool_call(
+    working_mode: WorkingMode,
+    input: &Input,
+    tool_call: &ChatCompletionMessageToolCall,
+    need_confirm: bool,
+) -> Result<()> {
+    let name = &tool_call.function.name;
+    if !need_confirm || input.allow_tool(name) {
+        return Ok(());
+    }
+    if working_mode.is_serve() {
+        bail!("Tool '{name}' requires confirmation, allow it with the '{ALLOW_TOOLS_HEADER}' header");
+    }
+    let args = match serde_json::from_str::<Value>(&tool_call.function.arguments) {
+        Ok(value) => serde_json::to_string_pretty(&value)?,
+        Err(_) => tool_call.function.arguments.clone(),
+    };
+    println!("\n{name} {args}");
+    let ans = Confirm::new(&format!("Run tool '{name}'?"))
+        .with_default(false)
+        .prompt()?;
+    if !ans {
+        bail!("The user declined to run tool '{name}'");
+    }
     Ok(())
 }
 
-pub async fn call_fn(
-    cmd: &str,
-    args: &str,
-    // envs: Option<HashMap<String, String>>,
-) -> Result<Value, Box<dyn std::error::Error>> {
-    let output = Command::new(cmd)
-    .arg(args)
-    .output()?;
-    let stdout = std::str::from_utf8(&output.stdout).context("Invalid UTF-8 in stdout")?;
-    let function_response = serde_json::from_str(stdout).context(r#"The crawler response is invalid. It should follow the JSON format: `[{"path":"...", "text":"..."}]`."#)?;
+pub async fn call_fn(cmd: &str, args: &str, sandbox: &ToolSandbox) -> Result<Value> {
+    // A relative program path would be looked up from the sandbox cwd
+    let program = match cmd.contains('/') && Path::new(cmd).is_relative() {
+        true => std::env::current_dir()?.join(cmd),
+        false => PathBuf::from(cmd),
+    };
+    let mut command = Command::new(program);
+    command
+        .arg(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = sandbox.working_dir()? {
+        let value = serde_json::from_str(args).unwrap_or_else(|_| Value::String(args.to_string()));
+        check_path_args(&value, &dir)?;
+        command.current_dir(dir);
+    }
+    if let Some(env) = &sandbox.env {
+        command.env_clear();
+        for name in env {
+            if let Ok(value) = std::env::var(name) {
+                command.env(name, value);
+            }
+        }
+    }
+    #[cfg(unix)]
+    if let Some(rlimits) = sandbox.rlimits.clone() {
+        unsafe {
+            command.pre_exec(move || set_rlimits(&rlimits));
+        }
+    }
+    // Its own process group, so that killing it also kills what it spawned
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = command.spawn().with_context(|| format!("Failed to run '{cmd}'"))?;
+    let mut stdout = child.stdout.take().context("No stdout")?;
+    let stderr = child.stderr.take().context("No stderr")?;
+    let max_output = sandbox.max_output;
+    // Drained concurrently so that a tool writing to both pipes never blocks
+    let mut read_err = tokio::spawn(read_head(stderr, max_output.unwrap_or(TOOL_STDERR_MAX)));
+
+    let run = async {
+        let mut out = Vec::new();
+        match max_output {
+            Some(max) => {
+                (&mut stdout).take(max as u64 + 1).read_to_end(&mut out).await?;
+                if out.len() > max {
+                    bail!("'{cmd}' was killed, output exceeded {max} bytes");
+                }
+            }
+            None => {
+                stdout.read_to_end(&mut out).await?;
+            }
+        }
+        let status = child.wait().await?;
+        let err = (&mut read_err).await??;
+        Ok((status, out, err))
+    };
+    let result = match sandbox.timeout {
+        Some(secs) => match timeout(Duration::from_secs(secs), run).await {
+            Ok(v) => v,
+            Err(_) => Err(anyhow!("'{cmd}' was killed, timed out after {secs}s")),
+        },
+        None => run.await,
+    };
+    if result.is_err() {
+        kill_tool(&mut child).await;
+        read_err.abort();
+    }
+    let (status, out, err) = result?;
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&err);
+        bail!("'{cmd}' exited with {status}: {}", stderr.trim());
+    }
+    let stdout = std::str::from_utf8(&out).context("Invalid UTF-8 in stdout")?;
+    let function_response = match serde_json::from_str(stdout) {
+        Ok(value) => value,
+        Err(_) => Value::String(stdout.trim().to_string()),
+    };
     Ok(function_response)
- }
+}
+
+/// Read `reader` to the end, keeping the first `max` bytes
+async fn read_head(mut reader: impl AsyncRead + Unpin, max: usize) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    (&mut reader).take(max as u64).read_to_end(&mut head).await?;
+    tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    Ok(head)
+}
+
+async fn kill_tool(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(unix)]
+fn set_rlimits(rlimits: &ToolRlimits) -> std::io::Result<()> {
+    let limits = [
+        (libc::RLIMIT_CPU, rlimits.cpu),
+        (libc::RLIMIT_AS, rlimits.memory),
+        (libc::RLIMIT_NOFILE, rlimits.nofile),
+    ];
+    for (resource, value) in limits {
+        if let Some(value) = value {
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tool_error(err: &anyhow::Error) -> Value {
+    json!({ "error": format!("{err:#}") })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_or_all_tools() {
+        let all_tools = || Ok(vec![ChatCompletionTool::default()]);
+        assert_eq!(or_all_tools(Ok(vec![]), all_tools).unwrap().len(), 0);
+        assert_eq!(or_all_tools(Err(anyhow!("Chroma is down")), all_tools).unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    fn script(name: &str, body: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ragents-{name}-{}.sh", std::process::id()));
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_call_fn_output_limits() {
+        let sandbox = ToolSandbox {
+            max_output: Some(1024),
+            ..Default::default()
+        };
+
+        let path = script("flood", "yes");
+        let err = call_fn(path.to_str().unwrap(), "{}", &sandbox).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("'{}' was killed, output exceeded 1024 bytes", path.display()));
+        fs::remove_file(path).unwrap();
+
+        let path = script("noisy", "yes | head -c 1000000 >&2\necho '{\"ok\":true}'");
+        let value = call_fn(path.to_str().unwrap(), "{}", &sandbox).await.unwrap();
+        assert_eq!(value, json!({"ok": true}));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_call_fn_cwd() {
+        let sandbox = ToolSandbox {
+            cwd: Some(std::env::temp_dir().display().to_string()),
+            ..Default::default()
+        };
+        let value = call_fn("config/tools/web/get_current_time.sh", "{}", &sandbox).await.unwrap();
+        assert!(value.get("current_time").is_some());
+
+        let err = call_fn("config/tools/web/get_current_time.sh", r#"{"path":"../etc"}"#, &sandbox).await.unwrap_err();
+        assert!(err.to_string().contains("outside of the tool's working directory"));
+    }
+}
@@ -0,0 +1,57 @@
+use async_openai::{config::OpenAIConfig, Client};
+use serde::Deserialize;
+
+/// An OpenAI-compatible provider, its models are addressed as `name:model`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientConfig {
+    pub name: String,
+    pub api_base: String,
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub models: Vec<ModelConfig>,
+}
+
+/// What we know about a model beyond its name
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelConfig {
+    pub name: String,
+    pub max_input_tokens: Option<usize>,
+}
+
+impl ClientConfig {
+    pub fn new(name: &str, api_base: &str, api_key: Option<&str>, models: &[ModelConfig]) -> Self {
+        Self {
+            name: name.to_string(),
+            api_base: api_base.to_string(),
+            api_key: api_key.map(|v| v.to_string()),
+            models: models.to_vec(),
+        }
+    }
+
+    pub fn build(&self) -> Client<OpenAIConfig> {
+        log::info!("client {} api_base addr:{}", self.name, self.api_base);
+        Client::with_config(
+            OpenAIConfig::new()
+                .with_api_key(self.api_key.clone().unwrap_or_default())
+                .with_api_base(&self.api_base),
+        )
+    }
+}
+
+/// A provider ready to serve requests
+#[derive(Debug, Clone)]
+pub struct LlmClient {
+    pub name: String,
+    pub client: Client<OpenAIConfig>,
+    pub models: Vec<ModelConfig>,
+}
+
+/// Split `provider:model` when `provider` is one of `clients`, the first client serves bare names
+pub fn resolve_model<'a>(clients: &'a [LlmClient], model: &str) -> Option<(&'a LlmClient, String)> {
+    if let Some((provider, name)) = model.split_once(':') {
+        if let Some(client) = clients.iter().find(|client| client.name == provider) {
+            return Some((client, name.to_string()));
+        }
+    }
+    clients.first().map(|client| (client, model.to_string()))
+}
@@ -1,36 +1,61 @@
-use std::{collections::HashMap, env, fs::{read_to_string, remove_file}, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, env, fs::{read_dir, read_to_string, remove_file, rename}, path::{Path, PathBuf}, sync::Arc};
 use anyhow::{bail, Context, Result};
-use async_openai::{config::OpenAIConfig, types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs, ChatCompletionTool}, Client};
+use async_openai::{config::OpenAIConfig, types::{ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, CompletionUsage, ChatCompletionRequestUserMessageArgs, ChatCompletionTool}, Client};
 use parking_lot::RwLock;
 use serde::Deserialize;
-use crate::realtime::prompt::render_prompt;
+use crate::{realtime::prompt::render_prompt, serve::auth::AuthConfig};
 
-use super::{agent::Agent, get_env_name, input::Input, normalize_env_name, session::Session, WorkingMode, AGENTS_DIR_NAME, LEFT_PROMPT, RIGHT_PROMPT, SESSIONS_DIR_NAME, TEMP_SESSION_NAME};
+use super::{agent::Agent, rag::Rag, role::Role, sampling::SamplingParams, client::{resolve_model, ClientConfig, LlmClient, ModelConfig}, error::RagentsError, tool::Tool, ensure_parent_exists, get_env_name, input::Input, normalize_env_name, session::Session, WorkingMode, AGENTS_DIR_NAME, CLIENT_TTL, DEFAULT_CLIENT_NAME, HISTORY_FILE_NAME, HISTORY_SIZE, LEFT_PROMPT, MAX_STEPS, RIGHT_PROMPT, ROLES_DIR_NAME, SESSIONS_DIR_NAME, TEMP_ROLE_NAME, TEMP_SESSION_NAME};
 
 pub type Config = Arc<RwLock<CConfig>>;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct CConfig {
-    pub api_base: String,
+    pub api_base: Option<String>,
     pub api_key: Option<String>,
+    /// Named providers, models are addressed as `provider:model`
+    #[serde(default)]
+    pub clients: Vec<ClientConfig>,
+    /// Metadata of the models served by `api_base`
+    #[serde(default)]
+    pub models: Vec<ModelConfig>,
     pub http_port: Option<u16>,
+    /// Seconds an idle HTTP client keeps its conversation state
+    pub client_ttl: Option<u64>,
 
     pub model: String,
-    pub temperature: Option<f64>,
-    pub top_p: Option<f64>,
+    #[serde(flatten)]
+    pub sampling: SamplingParams,
+    pub max_steps: Option<usize>,
+    /// Save sessions on exit, `false` never does and `null` asks
+    pub save_session: Option<bool>,
+    /// Summarize a session once it takes more tokens than this
+    pub compress_threshold: Option<usize>,
+    /// Lines kept in the Realtime history, `0` disables it
+    pub history_size: Option<usize>,
+    /// Keep a separate Realtime history for each agent
+    pub agent_history: Option<bool>,
 
     pub agents: HashMap<String, String>,
     pub tools: HashMap<String, String>,
     pub rags: HashMap<String, String>,
 
+    #[serde(default)]
+    pub auth: AuthConfig,
+
     #[serde(skip)]
     pub agent: Option<Agent>,
     #[serde(skip)]
+    pub role: Option<Role>,
+    /// A RAG used on its own, besides the agent's
+    #[serde(skip)]
+    pub rag: Option<Rag>,
+    #[serde(skip)]
     pub session: Option<Session>,
     #[serde(skip)]
     pub last_message: Option<(Input, String)>,
     #[serde(skip)]
-    pub client: Client<OpenAIConfig>,
+    pub llm_clients: Vec<LlmClient>,
     #[serde(skip)]
     pub working_mode: WorkingMode,
 }
@@ -44,39 +69,152 @@ impl CConfig {
     }
 
     pub fn create_client(&mut self) -> Result<()> {
-        let api_base = self.get_api_base();
-        let api_key = self.get_api_key();
-        log::info!("api_base addr:{}",&api_base);
-        log::info!("api_key addr:{}",&api_key);
-        let client: Client<OpenAIConfig> = Client::with_config(
-            OpenAIConfig::new()
-            .with_api_key(api_key)
-            .with_api_base(api_base)
-        );
-        self.client = client;
+        let client_configs = self.client_configs();
+        if client_configs.is_empty() {
+            bail!(RagentsError::Config("No LLM client, set 'api_base' or 'clients' in the config".into()));
+        }
+        self.llm_clients = client_configs
+            .iter()
+            .map(|client_config| LlmClient {
+                name: client_config.name.clone(),
+                client: client_config.build(),
+                models: client_config.models.clone(),
+            })
+            .collect();
         Ok(())
     }
 
+    /// The top-level `api_base` comes first as the default client, then `clients`
+    pub fn client_configs(&self) -> Vec<ClientConfig> {
+        let mut client_configs = vec![];
+        if let Some(api_base) = &self.api_base {
+            client_configs.push(ClientConfig::new(DEFAULT_CLIENT_NAME, api_base, self.api_key.as_deref(), &self.models));
+        }
+        client_configs.extend(self.clients.iter().cloned());
+        client_configs
+    }
+
+    /// The client serving `model` and the model name it expects
+    pub fn resolve_model(&self, model: &str) -> Result<(Client<OpenAIConfig>, String)> {
+        match resolve_model(&self.llm_clients, model) {
+            Some((llm_client, name)) => Ok((llm_client.client.clone(), name)),
+            None => bail!(RagentsError::Config(format!("No LLM client for model '{model}'"))),
+        }
+    }
+
+    /// The model in use, the agent's or role's own model wins over the global one
+    pub fn current_model(&self) -> String {
+        self.agent
+            .as_ref()
+            .and_then(|agent| agent.model.clone())
+            .or_else(|| self.role.as_ref().and_then(|role| role.model.clone()))
+            .unwrap_or_else(|| self.model.clone())
+    }
+
+    /// The messages to summarize when the session went over `compress_threshold`
+    pub fn session_to_compress(&self) -> Option<Vec<ChatCompletionRequestMessage>> {
+        let threshold = self.compress_threshold.filter(|v| *v > 0)?;
+        let session = self.session.as_ref()?;
+        let (tokens, _) = session.tokens_usage(self.max_input_tokens());
+        let messages = session.messages_to_compress();
+        if tokens <= threshold || messages.is_empty() {
+            return None;
+        }
+        Some(messages.to_vec())
+    }
+
+    pub fn session_to_autoname(&mut self) -> Option<Vec<ChatCompletionRequestMessage>> {
+        self.session.as_mut()?.take_autoname_messages()
+    }
+
+    pub fn set_session_autoname(&mut self, title: &str) {
+        if let Some(session) = self.session.as_mut() {
+            session.set_autoname(title);
+        }
+    }
+
+    pub fn compress_session(&mut self, summary: &str, count: usize) -> Result<()> {
+        match self.session.as_mut() {
+            Some(session) => session.compress(summary, count),
+            None => bail!(RagentsError::InvalidRequest("No session".into())),
+        }
+    }
+
+    /// Context window of the current model, from the `models` of its client
+    pub fn max_input_tokens(&self) -> Option<usize> {
+        let model = self.current_model();
+        let (llm_client, name) = resolve_model(&self.llm_clients, &model)?;
+        llm_client
+            .models
+            .iter()
+            .find(|model_config| model_config.name == name)
+            .and_then(|model_config| model_config.max_input_tokens)
+    }
+
+    /// Sampling parameters in effect, the session wins over the agent or role, those over the global ones
+    pub fn sampling(&self) -> SamplingParams {
+        let mut sampling = self.sampling.clone();
+        if let Some(role) = &self.role {
+            sampling = role.sampling.or(&sampling);
+        }
+        if let Some(agent) = &self.agent {
+            sampling = agent.sampling.or(&sampling);
+        }
+        if let Some(session) = &self.session {
+            sampling = session.sampling().or(&sampling);
+        }
+        sampling
+    }
+
+    /// Change a sampling parameter at the innermost level in use
+    pub fn set_sampling(&mut self, key: &str, value: &str) -> Result<()> {
+        if let Some(session) = self.session.as_mut() {
+            session.set_sampling(key, value)
+        } else if let Some(agent) = self.agent.as_mut() {
+            agent.sampling.set(key, value)
+        } else if let Some(role) = self.role.as_mut() {
+            role.sampling.set(key, value)
+        } else {
+            self.sampling.set(key, value)
+        }
+    }
+
+    /// Switch the model at runtime, this also replaces the agent's or role's own model
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+        if let Some(agent) = self.agent.as_mut() {
+            agent.model = None;
+        }
+        if let Some(role) = self.role.as_mut() {
+            role.model = None;
+        }
+    }
+
     pub fn get_http_addr(&self) -> Result<String> {
         let http_port = self.http_port.unwrap_or(8848);
         Ok(format!("0.0.0.0:{}",http_port))
     }
 
-    pub fn get_api_base(&self) -> String {
-        let api_base = self.api_base.clone();
-        api_base
-    }
-
-    pub fn get_api_key(&self) -> String {
-        let api_key = self.api_key.clone().unwrap_or("".to_string());
-        api_key
+    pub fn client_ttl(&self) -> u64 {
+        self.client_ttl.unwrap_or(CLIENT_TTL)
     }
 
-    pub fn sysinfo(&self) -> Result<String> {
-        let items = vec![
-            ("api_base", self.api_base.clone()),
-            ("model", self.model.clone()),
-        ];
+    /// `with_clients` adds the upstream `api_base` of each client, kept from HTTP callers
+    pub fn sysinfo(&self, with_clients: bool) -> Result<String> {
+        let mut items = vec![("model", self.current_model())];
+        if with_clients {
+            items.push(("clients", self.client_configs().iter().map(|v| format!("{}={}", v.name, v.api_base)).collect::<Vec<_>>().join(" ")));
+        }
+        if let Some(role) = &self.role {
+            items.push(("role", role.name.clone()));
+        }
+        items.extend(self.sampling().items());
+        if let Some(max_input_tokens) = self.max_input_tokens() {
+            items.push(("max_input_tokens", max_input_tokens.to_string()));
+        }
+        if let Some(session) = &self.session {
+            items.push(("session_tokens", session.usage().total_tokens.to_string()));
+        }
         let output = items
             .iter()
             .map(|(name, value)| format!("{name:<24}{value}\n"))
@@ -111,6 +249,20 @@ impl CConfig {
         }
     }
 
+    pub fn history_size(&self) -> usize {
+        self.history_size.unwrap_or(HISTORY_SIZE)
+    }
+
+    /// The history file of the agent in use when `agent_history` is on, the shared one otherwise
+    pub fn history_file(&self) -> PathBuf {
+        match &self.agent {
+            Some(agent) if self.agent_history.unwrap_or_default() => {
+                Self::agent_data_dir(&agent.name).join(HISTORY_FILE_NAME)
+            }
+            _ => Self::local_path(HISTORY_FILE_NAME),
+        }
+    }
+
     pub fn render_prompt_left(&self) -> String {
         let variables = self.generate_prompt_context();
         let left_prompt = LEFT_PROMPT;
@@ -125,7 +277,7 @@ impl CConfig {
 
     fn generate_prompt_context(&self) -> HashMap<&str, String> {
         let mut output = HashMap::new();
-        output.insert("model", self.model.clone());
+        output.insert("model", self.current_model());
         // output.insert("client_name", role.model().client_name().to_string());
         // output.insert("model_name", role.model().name().to_string());
         // output.insert(
@@ -135,22 +287,28 @@ impl CConfig {
         //         .unwrap_or_default()
         //         .to_string(),
         // );
-        if let Some(temperature) = self.temperature {
+        let sampling = self.sampling();
+        if let Some(temperature) = sampling.temperature {
             if temperature != 0.0 {
                 output.insert("temperature", temperature.to_string());
             }
         }
-        if let Some(top_p) = self.top_p {
+        if let Some(top_p) = sampling.top_p {
             if top_p != 0.0 {
                 output.insert("top_p", top_p.to_string());
             }
         }
         if let Some(session) = &self.session {
             output.insert("session", session.name.to_string());
-            // if let Some(autoname) = session.autoname() {
-            //     output.insert("session_autoname", autoname.to_string());
-            // }
+            if let Some(autoname) = session.autoname() {
+                output.insert("session_autoname", autoname.to_string());
+            }
             output.insert("dirty", session.dirty.to_string());
+            let (tokens, percent) = session.tokens_usage(self.max_input_tokens());
+            output.insert("consume_tokens", tokens.to_string());
+            if let Some(percent) = percent {
+                output.insert("consume_percent", format!("{percent:.2}"));
+            }
             // let (tokens, percent) = session.tokens_usage();
             // output.insert("consume_tokens", tokens.to_string());
             // output.insert("consume_percent", percent.to_string());
@@ -159,6 +317,13 @@ impl CConfig {
         if let Some(agent) = &self.agent {
             output.insert("agent", agent.name.to_string());
         }
+        if let Some(role) = &self.role {
+            output.insert("role", role.name.to_string());
+        }
+        let rags = self.active_rags();
+        if !rags.is_empty() {
+            output.insert("rag", rags.iter().map(|rag| rag.name.as_str()).collect::<Vec<&str>>().join(","));
+        }
 
         output.insert("color.reset", "\u{1b}[0m".to_string());
         output.insert("color.black", "\u{1b}[30m".to_string());
@@ -188,15 +353,20 @@ impl CConfig {
         Ok(())
     }
 
-    pub fn after_chat_completion(&mut self, input: &Input, output: &str) -> Result<()> {
+    pub fn after_chat_completion(&mut self, input: &Input, tool_messages: &[ChatCompletionRequestMessage], output: &str, usages: &[CompletionUsage]) -> Result<()> {
         self.last_message = Some((input.clone(), output.to_string()));
-        self.save_message(input, output)?;
+        self.save_message(input, tool_messages, output)?;
+        if let Some(session) = self.session.as_mut() {
+            for usage in usages {
+                session.record_usage(usage);
+            }
+        }
         Ok(())
     }
 
-    fn save_message(&mut self, input: &Input, output: &str) -> Result<()> {
+    fn save_message(&mut self, input: &Input, tool_messages: &[ChatCompletionRequestMessage], output: &str) -> Result<()> {
         if let Some(session) = &mut self.session {
-            session.add_message(&input, output)?;
+            session.add_message(input, tool_messages, output)?;
             return Ok(());
         }
         if output.is_empty() {
@@ -206,21 +376,44 @@ impl CConfig {
     }
 
     pub fn echo_message(&mut self, input: &Input) -> Result<Vec<ChatCompletionRequestMessage>> {
-        if let Some(session) = &mut self.session {
-            session.echo_messages(&input)
+        let mut messages = if input.is_web() {
+            input.request_messages()
+        } else if let Some(session) = &mut self.session {
+            session.echo_messages(input)?
         } else {
-            Ok(vec![ChatCompletionRequestUserMessageArgs::default().content(input.message_content()).build()?.into()])
-        }   
+            let mut messages: Vec<ChatCompletionRequestMessage> = vec![ChatCompletionRequestUserMessageArgs::default().content(input.message_content()).build()?.into()];
+            if let Some(output) = input.continue_output() {
+                messages.push(ChatCompletionRequestAssistantMessageArgs::default().content(output).build()?.into());
+            }
+            messages
+        };
+        let system_message = match (&self.agent, &self.role) {
+            (Some(agent), _) => agent.echo_system_message()?,
+            (None, Some(role)) => role.echo_system_message()?,
+            (None, None) => None,
+        };
+        if let Some(system_message) = system_message {
+            messages.insert(0, system_message);
+        }
+        Ok(messages)
     }
 
-    pub fn echo_tool(&mut self) -> Result<Vec<ChatCompletionTool>> {
-        if let Some(agent) = &mut self.agent {
+    pub fn echo_tool(&self) -> Result<Vec<ChatCompletionTool>> {
+        if let Some(agent) = &self.agent {
             agent.echo_tool()
         } else {
             Ok(vec![])
         }   
     }
 
+    pub fn max_steps(&self) -> usize {
+        self.agent
+            .as_ref()
+            .and_then(|agent| agent.max_steps)
+            .or(self.max_steps)
+            .unwrap_or(MAX_STEPS)
+    }
+
     pub fn sessions_dir(&self) -> PathBuf {
         match &self.agent {
             None => match env::var(get_env_name("sessions_dir")) {
@@ -238,6 +431,54 @@ impl CConfig {
         }
     }
 
+    pub fn list_sessions(&self) -> Vec<String> {
+        let mut names = list_yaml_names(&self.sessions_dir(), "");
+        names.sort();
+        names
+    }
+
+    /// Sessions of an agent other than the one in use, from its sessions dir
+    pub fn list_agent_sessions(&self, agent_name: &str) -> Vec<String> {
+        if !self.agents.contains_key(agent_name) {
+            return vec![];
+        }
+        let mut names = list_yaml_names(&Self::agent_data_dir(agent_name).join(SESSIONS_DIR_NAME), "");
+        names.sort();
+        names
+    }
+
+    pub fn load_session(&self, name: &str) -> Result<Session> {
+        let session_path = self.session_file(name);
+        if !session_path.exists() {
+            bail!(RagentsError::SessionNotFound(name.to_string()));
+        }
+        Session::load(name, &session_path)
+    }
+
+    pub fn rename_session(&self, name: &str, new_name: &str) -> Result<()> {
+        let session_path = self.session_file(name);
+        let new_session_path = self.session_file(new_name);
+        if !session_path.exists() {
+            bail!(RagentsError::SessionNotFound(name.to_string()));
+        }
+        if new_session_path.exists() {
+            bail!(RagentsError::SessionExists(new_name.to_string()));
+        }
+        ensure_parent_exists(&new_session_path)?;
+        rename(&session_path, &new_session_path)
+            .with_context(|| format!("Failed to rename session '{name}' to '{new_name}'"))?;
+        Ok(())
+    }
+
+    pub fn delete_session(&self, name: &str) -> Result<()> {
+        let session_path = self.session_file(name);
+        if !session_path.exists() {
+            bail!(RagentsError::SessionNotFound(name.to_string()));
+        }
+        remove_file(&session_path).with_context(|| format!("Failed to delete session '{name}'"))?;
+        Ok(())
+    }
+
     pub fn exit_session(&mut self) -> Result<()> {
         if let Some(mut session) = self.session.take() {
             let sessions_dir = self.sessions_dir();
@@ -251,10 +492,15 @@ impl CConfig {
         let session_name = match &self.session {
             Some(session) => match name {
                 Some(v) => v.to_string(),
-                None => "".to_string(),
+                None => session.name.clone(),
             },
-            None => bail!("No session"),
+            None => bail!(RagentsError::InvalidRequest("No session".into())),
         };
+        if session_name == TEMP_SESSION_NAME {
+            bail!(RagentsError::InvalidRequest(
+                "The temporary session needs a name to be saved, please run '.save session <name>'".into()
+            ));
+        }
         let session_path = self.session_file(&session_name);
         if let Some(session) = self.session.as_mut() {
             session.save(&session_name, &session_path, self.working_mode.is_realtime())?;
@@ -264,9 +510,9 @@ impl CConfig {
 
     pub fn use_session(&mut self, session_name: Option<&str>) -> Result<()> {
         if self.session.is_some() {
-            bail!(
-                "Already in a session, please run '.exit session' first to exit the current session."
-            );
+            bail!(RagentsError::InvalidRequest(
+                "Already in a session, please run '.exit session' first to exit the current session.".into()
+            ));
         }
         let session;
         match session_name {
@@ -305,6 +551,10 @@ impl CConfig {
         //     }
         // }
         self.session = session;
+        if let Some(session) = self.session.as_mut() {
+            session.set_save_session(self.save_session);
+        }
+        self.sync_session_role();
         // self.init_agent_session_variables()?;
         Ok(())
     }
@@ -316,30 +566,247 @@ impl CConfig {
             // }
             session.clear_messages();
         } else {
-            bail!("No session")
+            bail!(RagentsError::InvalidRequest("No session".into()))
         }
         self.last_message = None;
         Ok(())
     }
 
-    pub async fn use_agent(&mut self, agent_name: &str, session_name: Option<&str>) -> Result<()> {
+    pub fn use_agent(&mut self, agent_name: &str, session_name: Option<&str>) -> Result<()> {
         if self.agent.is_some() {
             bail!("Already in a agent, please run '.exit agent' first to exit the current agent.");
         }
+        if self.role.is_some() {
+            bail!("Already in a role, please run '.exit role' first to exit the current role.");
+        }
+        // The agent picks the sessions dir, it is dropped again when its session can't be opened
+        self.agent = Some(self.load_agent(agent_name)?);
+        if session_name.is_some() {
+            if let Err(err) = self.use_session(session_name) {
+                self.agent = None;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_agent(&self, agent_name: &str) -> Result<Agent> {
         match self.agents.get(agent_name) {
             Some(agent_path) => {
                 let mut agent = Agent::init(agent_name, agent_path)?;
                 agent.tool(&self.tools)?;
-                agent.rag(&self.tools)?;
-                self.agent = Some(agent);               
+                agent.rag(&self.rags)?;
+                Ok(agent)
             },
             None => {
-                bail!("No this agent");
+                bail!(RagentsError::AgentNotFound(agent_name.to_string()));
             }
         }
+    }
+
+    /// A copy of the config that answers with the given agent, leaving this one untouched
+    pub fn fork_with_agent(&self, agent_name: &str) -> Result<Self> {
+        let mut config = self.clone();
+        config.agent = Some(self.load_agent(agent_name)?);
+        config.session = None;
+        config.last_message = None;
+        Ok(config)
+    }
+
+    pub fn roles_dir() -> PathBuf {
+        match env::var(get_env_name("roles_dir")) {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => Self::local_path(ROLES_DIR_NAME),
+        }
+    }
+
+    /// `<name>.md` or else `<name>.yaml` in the roles dir
+    pub fn role_file(name: &str) -> Option<PathBuf> {
+        let roles_dir = Self::roles_dir();
+        ["md", "yaml"]
+            .iter()
+            .map(|ext| roles_dir.join(format!("{name}.{ext}")))
+            .find(|path| path.exists())
+    }
+
+    pub fn load_role(&self, role_name: &str) -> Result<Role> {
+        match Self::role_file(role_name) {
+            Some(role_path) => Role::init(role_name, &role_path),
+            None => bail!(RagentsError::InvalidRequest(format!("No role '{role_name}'"))),
+        }
+    }
+
+    pub fn use_role(&mut self, role_name: &str) -> Result<()> {
+        let role = self.load_role(role_name)?;
+        self.set_role(role)
+    }
+
+    /// Use `prompt` as the system prompt through a temporary role
+    pub fn use_prompt(&mut self, prompt: &str) -> Result<()> {
+        self.set_role(Role::new(TEMP_ROLE_NAME, prompt))
+    }
+
+    fn set_role(&mut self, role: Role) -> Result<()> {
+        if self.agent.is_some() {
+            bail!("Already in a agent, please run '.exit agent' first to exit the current agent.");
+        }
+        self.role = Some(role);
+        self.sync_session_role();
+        Ok(())
+    }
+
+    pub fn exit_role(&mut self) -> Result<()> {
+        if self.role.take().is_none() {
+            bail!("No role");
+        }
+        if let Some(session) = self.session.as_mut() {
+            session.set_role(None);
+        }
         Ok(())
     }
 
+    /// Record the current role in the session, or bring back the role the session was held with
+    fn sync_session_role(&mut self) {
+        let session_role = match &self.session {
+            Some(session) => session.role().map(|v| v.to_string()),
+            None => return,
+        };
+        match (&self.role, session_role) {
+            (Some(role), _) => {
+                let role_name = (!role.is_temp()).then(|| role.name.clone());
+                if let Some(session) = self.session.as_mut() {
+                    session.set_role(role_name.as_deref());
+                }
+            }
+            (None, Some(role_name)) if self.agent.is_none() => match self.load_role(&role_name) {
+                Ok(role) => self.role = Some(role),
+                Err(err) => log::warn!("Failed to restore role of the session: {err}"),
+            },
+            _ => {}
+        }
+    }
+
+    pub fn list_role_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        let entries = match read_dir(Self::roles_dir()) {
+            Ok(v) => v,
+            Err(_) => return names,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let (Some(name), Some(ext)) = (path.file_stem(), path.extension()) {
+                if ext == "md" || ext == "yaml" {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn list_roles(&self) -> Vec<(String, String)> {
+        self.list_role_names()
+            .into_iter()
+            .map(|name| match self.load_role(&name) {
+                Ok(role) => {
+                    let info = role.prompt.lines().next().unwrap_or_default().to_string();
+                    (name, info)
+                }
+                Err(err) => (name, format!("invalid: {err}")),
+            })
+            .collect()
+    }
+
+    pub fn use_rag(&mut self, rag_name: &str) -> Result<()> {
+        let rag_path = match self.rags.get(rag_name) {
+            Some(v) => v,
+            None => bail!(RagentsError::InvalidRequest(format!("No rag '{rag_name}'"))),
+        };
+        self.rag = Some(Rag::init(rag_name, rag_path)?);
+        Ok(())
+    }
+
+    pub fn exit_rag(&mut self) -> Result<()> {
+        if self.rag.take().is_none() {
+            bail!("No rag");
+        }
+        Ok(())
+    }
+
+    /// RAGs searched for each question, the agent's then the standalone one
+    pub fn active_rags(&self) -> Vec<Rag> {
+        let mut rags = match &self.agent {
+            Some(agent) => agent.rag.clone(),
+            None => vec![],
+        };
+        if let Some(rag) = &self.rag {
+            if !rags.iter().any(|v| v.name == rag.name) {
+                rags.push(rag.clone());
+            }
+        }
+        rags
+    }
+
+    pub fn list_agents(&self) -> Vec<(String, String)> {
+        let mut rows: Vec<(String, String)> = self
+            .agents
+            .iter()
+            .map(|(name, path)| match Agent::init(name, path) {
+                Ok(agent) => (name.clone(), agent.description),
+                Err(err) => (name.clone(), format!("invalid: {err}")),
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    pub fn list_rags(&self) -> Vec<(String, String)> {
+        let mut rows: Vec<(String, String)> = self
+            .rags
+            .iter()
+            .map(|(name, path)| match Rag::init(name, path) {
+                Ok(rag) => (name.clone(), format!("{} documents, {}", rag.documents.len(), rag.rag_embedding_model)),
+                Err(err) => (name.clone(), format!("invalid: {err}")),
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    pub fn list_tools(&self) -> Vec<(String, String)> {
+        let mut rows: Vec<(String, String)> = self
+            .tools
+            .iter()
+            .map(|(name, path)| match Tool::init(name, path) {
+                Ok(tool) => {
+                    let mut functions: Vec<&str> = tool.tool_json.keys().map(|v| v.as_str()).collect();
+                    functions.sort();
+                    (name.clone(), functions.join(", "))
+                }
+                Err(err) => (name.clone(), format!("invalid: {err}")),
+            })
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    /// Saved sessions with their generated title when it is not already part of the name
+    pub fn list_sessions_with_autoname(&self) -> Vec<(String, String)> {
+        self.list_sessions()
+            .into_iter()
+            .map(|name| {
+                let autoname = self
+                    .load_session(&name)
+                    .ok()
+                    .and_then(|session| session.autoname().map(|v| v.to_string()))
+                    .filter(|autoname| !name.ends_with(autoname.as_str()))
+                    .unwrap_or_default();
+                (name, autoname)
+            })
+            .collect()
+    }
+
     pub fn exit_agent(&mut self) -> Result<()> {
         self.exit_session()?;
         if self.agent.take().is_some() {
@@ -348,11 +815,36 @@ impl CConfig {
         Ok(())
     }
 
-    pub fn tool_exec(&mut self, name: &str) -> Option<&str>{
+    pub fn tool_exec(&self, name: &str) -> Option<&str> {
         if let Some(agent) = &self.agent {
             return agent.tool_exec(name)
         }
         None
     }
 
+    pub fn find_tool(&self, name: &str) -> Option<&Tool> {
+        if let Some(agent) = &self.agent {
+            return agent.find_tool(name)
+        }
+        None
+    }
+
+}
+
+fn list_yaml_names(dir: &Path, prefix: &str) -> Vec<String> {
+    let mut names = vec![];
+    let entries = match read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return names,
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        if path.is_dir() {
+            names.extend(list_yaml_names(&path, &format!("{prefix}{file_name}/")));
+        } else if let Some(name) = file_name.strip_suffix(".yaml") {
+            names.push(format!("{prefix}{name}"));
+        }
+    }
+    names
 }
@@ -3,16 +3,39 @@ use chromadb::v2::{collection::QueryOptions, embeddings::openai::{OpenAIConfig,
 use async_trait::async_trait;
 use anyhow::Result;
 
+#[derive(Debug, Clone)]
+pub struct RagChunk {
+    pub id: String,
+    pub document: String,
+    pub distance: f32,
+}
+
+impl RagChunk {
+    /// The document path the chunk was split from, see `rags_load_chromadb`
+    pub fn source(&self) -> &str {
+        match self.id.rsplit_once("-id-") {
+            Some((source, _)) => source,
+            None => &self.id,
+        }
+    }
+}
+
 #[async_trait]
 pub trait Database {
-    async fn query_tool(&self, name: &str, query: &str, embedding_function: Option<&str>) -> Result<Vec<String>, Box<dyn Error>>;
-    async fn query_rag(&self, name: &str, query: &str, embedding_function: Option<&str>) -> Result<Vec<String>, Box<dyn Error>>;
+    async fn query_tool(&self, name: &str, query: &str, top_k: usize, embedding_function: Option<&str>) -> Result<Vec<(String, f32)>, Box<dyn Error>>;
+    async fn query_rag(&self, name: &str, query: &str, top_k: usize, embedding_function: Option<&str>) -> Result<Vec<RagChunk>, Box<dyn Error>>;
 }
 
 pub struct Chroma {
     client: ChromaClient,
 }
 
+impl Default for Chroma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chroma {
     pub fn new() -> Self {
         let client = ChromaClient::new(Default::default());
@@ -24,45 +47,54 @@ impl Chroma {
 
 #[async_trait]
 impl Database for Chroma {
-    async fn query_tool(&self, name: &str, query: &str, embedding_function: Option<&str>) -> Result<Vec<String>, Box<dyn Error>>{
+    async fn query_tool(&self, name: &str, query: &str, top_k: usize, embedding_function: Option<&str>) -> Result<Vec<(String, f32)>, Box<dyn Error>>{
         let collection = self.client.get_collection(name).await?;
             let query = QueryOptions {
                 query_texts: Some(vec![query]),
                 query_embeddings: None,
                 where_metadata: None,
                 where_document: None,
-                n_results: Some(5),
+                n_results: Some(top_k),
                 include: None,
             };
     
             let openai_embeddings = OpenAIEmbeddings::new(OpenAIConfig{ 
                 api_endpoint: format!("{}{}", std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| "".to_string()), "/embeddings"),
-                api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()).into(),
+                api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()),
                 model: embedding_function.unwrap_or("bge-large").to_string(),
             });
             let query_result = collection.query(query, Some(Box::new(openai_embeddings))).await?;
-        Ok(query_result.ids.into_iter().flatten().collect())
+        let ids = query_result.ids.into_iter().flatten();
+        let distances = query_result.distances.unwrap_or_default().into_iter().flatten().chain(std::iter::repeat(0.0));
+        Ok(ids.zip(distances).collect())
     }
 
-    async fn query_rag(&self, name: &str, query: &str, embedding_function: Option<&str>) -> Result<Vec<String>, Box<dyn Error>> {
+    async fn query_rag(&self, name: &str, query: &str, top_k: usize, embedding_function: Option<&str>) -> Result<Vec<RagChunk>, Box<dyn Error>> {
         let collection = self.client.get_collection(name).await?;
             let query = QueryOptions {
                 query_texts: Some(vec![query]),
                 query_embeddings: None,
                 where_metadata: None,
                 where_document: None,
-                n_results: Some(5),
+                n_results: Some(top_k),
                 include: None,
             };
     
             let openai_embeddings = OpenAIEmbeddings::new(OpenAIConfig{ 
                 api_endpoint: format!("{}{}", std::env::var("OPENAI_API_BASE").unwrap_or_else(|_| "".to_string()), "/embeddings"),
-                api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()).into(),
+                api_key: std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string()),
                 model: embedding_function.unwrap_or("bge-large").to_string(),
             });
             let query_result = collection.query(query, Some(Box::new(openai_embeddings))).await?;
-        let documents = query_result.documents.unwrap_or(vec![]);
-        Ok(documents.into_iter().flatten().collect())
+        let ids = query_result.ids.into_iter().flatten();
+        let documents = query_result.documents.unwrap_or_default().into_iter().flatten();
+        let distances = query_result.distances.unwrap_or_default().into_iter().flatten().chain(std::iter::repeat(0.0));
+        let chunks = ids
+            .zip(documents)
+            .zip(distances)
+            .map(|((id, document), distance)| RagChunk { id, document, distance })
+            .collect();
+        Ok(chunks)
     }
 }
 
@@ -0,0 +1,224 @@
+use async_openai::error::OpenAIError;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Errors that callers can tell apart, each maps to an HTTP status in serve mode
+#[derive(Debug, Clone, Error)]
+pub enum RagentsError {
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("{0}")]
+    InvalidApiKey(String),
+    #[error("The API key is not allowed to use '{0}'")]
+    ModelNotAllowed(String),
+    #[error("No agent '{0}'")]
+    AgentNotFound(String),
+    #[error("No session '{0}'")]
+    SessionNotFound(String),
+    #[error("{0}")]
+    ClientNotFound(String),
+    #[error("Session '{0}' already exists")]
+    SessionExists(String),
+    #[error("{0}")]
+    Session(String),
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Tool(String),
+    #[error("{0}")]
+    Rag(String),
+    #[error("{0}")]
+    RateLimited(String),
+    #[error("{0}")]
+    Upstream(String),
+    #[error("{0}")]
+    Timeout(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl RagentsError {
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::InvalidRequest(_) => 400,
+            Self::InvalidApiKey(_) => 401,
+            Self::ModelNotAllowed(_) => 403,
+            Self::AgentNotFound(_) | Self::SessionNotFound(_) | Self::ClientNotFound(_) => 404,
+            Self::SessionExists(_) => 409,
+            Self::RateLimited(_) => 429,
+            Self::Rag(_) | Self::Upstream(_) => 502,
+            Self::Timeout(_) => 504,
+            Self::Session(_) | Self::Config(_) | Self::Tool(_) | Self::Internal(_) => 500,
+        }
+    }
+
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_)
+            | Self::InvalidApiKey(_)
+            | Self::ModelNotAllowed(_)
+            | Self::AgentNotFound(_)
+            | Self::SessionNotFound(_)
+            | Self::ClientNotFound(_)
+            | Self::SessionExists(_) => "invalid_request_error",
+            Self::RateLimited(_) => "rate_limit_error",
+            Self::Rag(_) | Self::Upstream(_) => "upstream_error",
+            Self::Timeout(_) => "timeout_error",
+            Self::Session(_) | Self::Config(_) | Self::Tool(_) | Self::Internal(_) => "server_error",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidApiKey(_) => "invalid_api_key",
+            Self::ModelNotAllowed(_) => "model_not_allowed",
+            Self::AgentNotFound(_) => "model_not_found",
+            Self::SessionNotFound(_) => "session_not_found",
+            Self::ClientNotFound(_) => "client_not_found",
+            Self::SessionExists(_) => "session_exists",
+            Self::Session(_) => "session_error",
+            Self::Config(_) => "config_error",
+            Self::Tool(_) => "tool_error",
+            Self::Rag(_) => "rag_error",
+            Self::RateLimited(_) => "rate_limit_exceeded",
+            Self::Upstream(_) => "upstream_error",
+            Self::Timeout(_) => "timeout",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn param(&self) -> Option<&'static str> {
+        match self {
+            Self::ModelNotAllowed(_) | Self::AgentNotFound(_) => Some("model"),
+            _ => None,
+        }
+    }
+
+    /// Find the kind of an error, looking through its context chain
+    pub fn classify(err: &anyhow::Error) -> Self {
+        let message = format!("{err:#}");
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<RagentsError>() {
+                return err.clone();
+            }
+            if let Some(err) = cause.downcast_ref::<OpenAIError>() {
+                return Self::from_upstream(err, message);
+            }
+            if cause.is::<tokio::time::error::Elapsed>() {
+                return Self::Timeout(message);
+            }
+        }
+        Self::Internal(message)
+    }
+
+    fn from_upstream(err: &OpenAIError, message: String) -> Self {
+        match err {
+            OpenAIError::Reqwest(err) if err.is_timeout() => Self::Timeout(message),
+            OpenAIError::ApiError(err) => {
+                let code = err.code.as_deref().unwrap_or_default();
+                let r#type = err.r#type.as_deref().unwrap_or_default();
+                if code == "rate_limit_exceeded" || code == "insufficient_quota" || r#type.contains("rate_limit") {
+                    Self::RateLimited(message)
+                } else if r#type == "invalid_request_error" {
+                    Self::InvalidRequest(message)
+                } else {
+                    Self::Upstream(message)
+                }
+            }
+            // The SSE client reports `Invalid status code: 429 Too Many Requests`
+            OpenAIError::StreamError(text) if text.contains("status code: 429") => Self::RateLimited(message),
+            OpenAIError::StreamError(text) if text.contains("status code: 504") => Self::Timeout(message),
+            OpenAIError::InvalidArgument(_) => Self::InvalidRequest(message),
+            _ => Self::Upstream(message),
+        }
+    }
+}
+
+/// An error as sent to API clients, in the OpenAI `{"error": {...}}` shape
+#[derive(Debug, Clone)]
+pub struct ErrorResponse {
+    pub status: u16,
+    /// Shown to clients, generic for server side failures
+    pub message: String,
+    /// The whole error chain, for the server log
+    pub detail: String,
+    pub r#type: &'static str,
+    pub code: &'static str,
+    pub param: Option<&'static str>,
+}
+
+impl ErrorResponse {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let kind = RagentsError::classify(err);
+        let detail = format!("{err:#}");
+        let message = match kind.status() {
+            500 => "The server had an error while processing your request".to_string(),
+            502 => "The upstream service failed to answer the request".to_string(),
+            _ => detail.clone(),
+        };
+        Self {
+            status: kind.status(),
+            message,
+            detail,
+            r#type: kind.error_type(),
+            code: kind.code(),
+            param: kind.param(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "error": {
+                "message": self.message,
+                "type": self.r#type,
+                "param": self.param,
+                "code": self.code,
+            }
+        })
+    }
+}
+
+impl From<anyhow::Error> for ErrorResponse {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(&err)
+    }
+}
+
+impl From<RagentsError> for ErrorResponse {
+    fn from(err: RagentsError) -> Self {
+        Self::new(&err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use async_openai::error::ApiError;
+
+    #[test]
+    fn test_classify() {
+        let err = Err::<(), _>(RagentsError::SessionNotFound("demo".into()))
+            .context("Failed to open")
+            .unwrap_err();
+        let response = ErrorResponse::new(&err);
+        assert_eq!(response.status, 404);
+        assert_eq!(response.code, "session_not_found");
+        assert_eq!(response.message, "Failed to open: No session 'demo'");
+
+        let err = anyhow::Error::from(OpenAIError::ApiError(ApiError {
+            message: "Slow down".into(),
+            r#type: Some("requests".into()),
+            param: None,
+            code: Some("rate_limit_exceeded".into()),
+        }));
+        assert_eq!(ErrorResponse::new(&err).status, 429);
+
+        let err = anyhow::anyhow!("boom");
+        let response = ErrorResponse::new(&err);
+        assert_eq!(response.status, 500);
+        assert_eq!(response.detail, "boom");
+        assert!(!response.message.contains("boom"));
+    }
+}
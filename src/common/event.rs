@@ -0,0 +1,78 @@
+use anyhow::Result;
+use async_openai::types::CompletionUsage;
+use parking_lot::Mutex;
+use serde_json::Value;
+
+use super::{error::ErrorResponse, tokens::sum_usages};
+
+/// Everything `ask` reports while answering
+#[derive(Debug, Clone)]
+pub enum AskEvent {
+    TextDelta(String),
+    ToolCallStarted {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+    ToolCallFinished {
+        id: String,
+        name: String,
+        result: Value,
+    },
+    Usage(CompletionUsage),
+    Sources(Vec<String>),
+    Error(ErrorResponse),
+    Done,
+}
+
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: AskEvent) -> Result<()>;
+}
+
+/// Keeps every event, for tests and library embedding
+#[derive(Debug, Default)]
+pub struct CollectSink {
+    events: Mutex<Vec<AskEvent>>,
+}
+
+impl CollectSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<AskEvent> {
+        self.events.lock().clone()
+    }
+
+    pub fn text(&self) -> String {
+        self.events
+            .lock()
+            .iter()
+            .filter_map(|event| match event {
+                AskEvent::TextDelta(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Usage summed over every `Usage` event
+    pub fn usage(&self) -> Option<CompletionUsage> {
+        let usages: Vec<CompletionUsage> = self
+            .events
+            .lock()
+            .iter()
+            .filter_map(|event| match event {
+                AskEvent::Usage(usage) => Some(usage.clone()),
+                _ => None,
+            })
+            .collect();
+        sum_usages(&usages)
+    }
+}
+
+impl EventSink for CollectSink {
+    fn emit(&self, event: AskEvent) -> Result<()> {
+        self.events.lock().push(event);
+        Ok(())
+    }
+}
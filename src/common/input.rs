@@ -1,6 +1,10 @@
-use std::collections::HashMap;
 
-use async_openai::types::CreateChatCompletionRequest;
+use std::fs::read_to_string;
+
+use anyhow::{Context, Result};
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart, CreateChatCompletionRequest};
+
+use super::{config::Config, expand_path, rag::{rag_template, search_rags}};
 
 #[derive(Debug, Clone)]
 pub struct Input {
@@ -10,15 +14,17 @@ pub struct Input {
     continue_output: Option<String>,
     regenerate: bool,
     medias: Vec<String>,
-    data_urls: HashMap<String, String>,
     // tool_calls: Option<MessageContentToolCalls>,
     rag_name: Option<String>,
+    rag_sources: Vec<String>,
+    allow_tools: Vec<String>,
     // role: Role,
     // with_session: bool,
     // with_agent: bool,
 }
 
 impl Input {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(text: &str) -> Self {
         // let (role, with_session, with_agent) = resolve_role(&config.read(), role);
         Self {
@@ -29,9 +35,10 @@ impl Input {
             continue_output: None,
             regenerate: false,
             medias: Default::default(),
-            data_urls: Default::default(),
             // tool_calls: None,
             rag_name: None,
+            rag_sources: Default::default(),
+            allow_tools: Default::default(),
             // with_session: todo!(),
             // with_agent: todo!(),
             // role,
@@ -43,15 +50,16 @@ impl Input {
         // let (role, with_session, with_agent) = resolve_role(&config.read(), role);
         Self {
             // config: config.clone(),
-            text: Default::default(),
+            text: last_user_text(&request.messages).unwrap_or_default(),
             request: request.clone(),
             patched_text: None,
             continue_output: None,
             regenerate: false,
             medias: Default::default(),
-            data_urls: Default::default(),
             // tool_calls: None,
             rag_name: None,
+            rag_sources: Default::default(),
+            allow_tools: Default::default(),
             // with_session: todo!(),
             // with_agent: todo!(),
             // role,
@@ -60,16 +68,130 @@ impl Input {
         }
     }
 
+    /// The contents of the files at `paths`, each in a fenced block, followed by `text`
+    pub fn from_files(paths: &[&str], text: &str) -> Result<Self> {
+        let mut parts = vec![];
+        for path in paths {
+            let content = read_to_string(expand_path(path))
+                .with_context(|| format!("Failed to read file '{path}'"))?;
+            parts.push(format!("```{path}\n{}\n```", content.trim_end()));
+        }
+        if !text.is_empty() {
+            parts.push(text.to_string());
+        }
+        Ok(Self::from_str(&parts.join("\n\n")))
+    }
 
     pub fn is_empty(&self) -> bool {
         self.text.is_empty() && self.medias.is_empty()
     }
 
     pub fn message_content(&self) -> &str {
-        &self.text
+        self.patched_text.as_deref().unwrap_or(&self.text)
+    }
+
+    pub fn is_web(&self) -> bool {
+        !self.request.messages.is_empty()
+    }
+
+    /// The messages of a web request, with the last user message replaced by `message_content`
+    pub fn request_messages(&self) -> Vec<ChatCompletionRequestMessage> {
+        let mut messages = self.request.messages.clone();
+        if self.patched_text.is_some() {
+            if let Some(ChatCompletionRequestMessage::User(message)) = messages
+                .iter_mut()
+                .rev()
+                .find(|message| matches!(message, ChatCompletionRequestMessage::User(_)))
+            {
+                message.content = ChatCompletionRequestUserMessageContent::Text(self.message_content().to_string());
+            }
+        }
+        messages
+    }
+
+    pub fn rag_name(&self) -> Option<&str> {
+        self.rag_name.as_deref()
+    }
+
+    pub fn rag_sources(&self) -> &[String] {
+        &self.rag_sources
+    }
+
+    pub async fn use_rag(&mut self, config: &Config) -> Result<()> {
+        if self.text.is_empty() {
+            return Ok(());
+        }
+        let rags = config.read().active_rags();
+        if rags.is_empty() {
+            return Ok(());
+        }
+        let chunks = search_rags(&rags, &self.text).await?;
+        let embeddings = chunks
+            .iter()
+            .map(|chunk| chunk.document.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n\n");
+        let mut sources: Vec<String> = vec![];
+        for chunk in chunks.iter() {
+            if !sources.iter().any(|v| v == chunk.source()) {
+                sources.push(chunk.source().to_string());
+            }
+        }
+        self.patched_text = Some(rag_template(&embeddings, &self.text));
+        self.rag_name = Some(rags.iter().map(|rag| rag.name.as_str()).collect::<Vec<&str>>().join(","));
+        self.rag_sources = sources;
+        Ok(())
+    }
+
+    pub fn continue_output(&self) -> Option<&str> {
+        self.continue_output.as_deref()
+    }
+
+    /// Ask the model to carry on from `output`, the answer so far to this input
+    pub fn set_continue_output(&mut self, output: &str) {
+        self.continue_output = Some(output.to_string());
+    }
+
+    /// Whether this input replaces the last turn of the session
+    pub fn replaces_last_turn(&self) -> bool {
+        self.regenerate || self.continue_output.is_some()
     }
 
     pub fn regenerate(&self) -> bool {
         self.regenerate
     }
-}
\ No newline at end of file
+
+    /// Answer this input anew, dropping any output it was continued from
+    pub fn set_regenerate(&mut self) {
+        self.regenerate = true;
+        self.continue_output = None;
+    }
+
+    pub fn set_allow_tools(&mut self, allow_tools: Vec<String>) {
+        self.allow_tools = allow_tools;
+    }
+
+    pub fn allow_tool(&self, name: &str) -> bool {
+        self.allow_tools.iter().any(|v| v == name)
+    }
+}
+
+fn last_user_text(messages: &[ChatCompletionRequestMessage]) -> Option<String> {
+    let content = messages.iter().rev().find_map(|message| match message {
+        ChatCompletionRequestMessage::User(message) => Some(&message.content),
+        _ => None,
+    })?;
+    match content {
+        ChatCompletionRequestUserMessageContent::Text(text) => Some(text.clone()),
+        ChatCompletionRequestUserMessageContent::Array(parts) => Some(
+            parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatCompletionRequestUserMessageContentPart::Text(part) => Some(part.text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        ),
+    }
+}
@@ -1,22 +1,47 @@
-use std::{fs::create_dir_all, path::Path};
+use std::{fs::create_dir_all, path::{Path, PathBuf}};
 
 use anyhow::{anyhow, Context, Result};
 
 pub mod config;
+pub mod client;
 pub mod agent;
+pub mod role;
 pub mod rag;
 pub mod tool;
 pub mod session;
+pub mod sampling;
+pub mod tokens;
 pub mod input;
 pub mod ask;
+pub mod event;
+pub mod error;
 pub mod loader;
 pub mod db;
 
-const TEMP_SESSION_NAME: &str = "temp";
-const LEFT_PROMPT: &str = "{color.green}{?session {?agent {agent}>}{session}{?role /}}{!session {?agent {agent}>}}{role}{?rag @{rag}}{color.cyan}{?session )}{!session >}{color.reset} ";
-const RIGHT_PROMPT: &str = "{color.purple}{?session {?consume_tokens {consume_tokens}({consume_percent}%)}{!consume_tokens {consume_tokens}}}{color.reset}";
+pub const TEMP_SESSION_NAME: &str = "temp";
+const TEMP_ROLE_NAME: &str = "%%";
+const LEFT_PROMPT: &str = "{color.green}{?session {?agent {agent}>}{session}{?session_autoname :{session_autoname}}{?role /}}{!session {?agent {agent}>}}{role}{?rag @{rag}}{color.cyan}{?session )}{!session >}{color.reset} ";
+const RIGHT_PROMPT: &str = "{color.purple}{?session {?consume_tokens {consume_tokens}{?consume_percent ({consume_percent}%)}}}{color.reset}";
 const SESSIONS_DIR_NAME: &str = "sessions";
 const AGENTS_DIR_NAME: &str = "agents";
+const ROLES_DIR_NAME: &str = "roles";
+const HISTORY_FILE_NAME: &str = "history.txt";
+const HISTORY_SIZE: usize = 1000;
+const MAX_STEPS: usize = 10;
+const TOOL_TOP_K: usize = 5;
+/// Last user turns, with their answers and tool messages, a session keeps verbatim when compressed
+const COMPRESS_KEEP_TURNS: usize = 2;
+/// Bytes of a tool's stderr kept for the error message when `max_output` is unset
+const TOOL_STDERR_MAX: usize = 64 * 1024;
+pub const ALLOW_TOOLS_HEADER: &str = "X-Ragents-Allow-Tools";
+pub const AGENT_MODEL_PREFIX: &str = "agent:";
+pub const CLIENT_HEADER: &str = "X-Ragents-Client";
+pub const CLIENT_COOKIE: &str = "ragents_client";
+const CLIENT_TTL: u64 = 3600;
+const SUMMARIZE_PROMPT: &str = "Summarize the discussion briefly in 200 words or less to use as a prompt for future context.";
+const AUTONAME_PROMPT: &str = "Create a title for the conversation above in 3 to 6 words, reply with the title only.";
+const SUMMARY_PROMPT: &str = "This is a summary of the chat history as a recap: ";
+pub const DEFAULT_CLIENT_NAME: &str = "default";
 
 const RAG_TEMPLATE: &str = r#"Answer the query based on the context while respecting the rules. (user query, some textual context and rules, all inside xml tags)
 
@@ -45,6 +70,14 @@ pub fn normalize_env_name(value: &str) -> String {
     value.replace('-', "_").to_ascii_uppercase()
 }
 
+/// `path` with a leading `~/` resolved against the home directory
+pub fn expand_path(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().unwrap_or_default().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
 pub fn ensure_parent_exists(path: &Path) -> Result<()> {
     if path.exists() {
         return Ok(());
@@ -64,8 +97,9 @@ pub fn ensure_parent_exists(path: &Path) -> Result<()> {
 }
 
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 pub enum WorkingMode {
+    #[default]
     Realtime,
     Serve,
 }
@@ -78,8 +112,3 @@ impl WorkingMode {
         *self == WorkingMode::Serve
     }
 }
-impl Default for WorkingMode {
-    fn default() -> Self {
-        WorkingMode::Realtime
-    }
-}
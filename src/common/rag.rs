@@ -1,21 +1,107 @@
 use std::fs::read_to_string;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use futures::future::try_join_all;
 use serde::Deserialize;
 
+use super::{error::RagentsError, RAG_TEMPLATE};
+use super::db::db::{create_database, RagChunk};
+
 #[derive(Default, Debug, Clone, Deserialize)]
 pub struct Rag {
+    #[serde(skip)]
+    pub name: String,
     pub rag_embedding_model: String,
     pub rag_top_k: usize,
     pub rag_chunk_size: usize,
     pub rag_chunk_overlap: usize,
+    /// Multiplier applied to chunk scores when merging several RAGs
+    #[serde(default = "default_rag_weight")]
+    pub rag_weight: f32,
     pub documents: Vec<String>,
 }
 
+fn default_rag_weight() -> f32 {
+    1.0
+}
+
 impl Rag {
     pub fn init(rag_name: &str, rag_path: &str) -> Result<Self>  {
         let err = || format!("Failed to load config at '{}'", rag_name);
         let content = read_to_string(rag_path).with_context(err)?;
-        let config = serde_yaml::from_str(&content)?;
+        let mut config: Self = serde_yaml::from_str(&content)?;
+        config.name = rag_name.to_string();
         Ok(config)
     }
+
+    pub async fn search(&self, query: &str) -> Result<Vec<RagChunk>> {
+        let database = create_database("chromadb");
+        database
+            .query_rag(&self.name, query, self.rag_top_k, Some(&self.rag_embedding_model))
+            .await
+            .map_err(|err| anyhow!(RagentsError::Rag(format!("Failed to query rag '{}': {err}", self.name))))
+    }
+}
+
+/// Put the retrieved chunks around the user's text
+pub fn rag_template(embeddings: &str, text: &str) -> String {
+    if embeddings.is_empty() {
+        return text.to_string();
+    }
+    RAG_TEMPLATE
+        .replace("__CONTEXT__", embeddings)
+        .replace("__INPUT__", text)
+}
+
+/// Query every RAG concurrently and merge the chunks, best weighted score first
+pub async fn search_rags(rags: &[Rag], query: &str) -> Result<Vec<RagChunk>> {
+    let results = try_join_all(rags.iter().map(|rag| rag.search(query))).await?;
+    Ok(merge_chunks(rags, results))
+}
+
+/// Order the chunks of every RAG by their weighted closeness, `results` follows `rags`
+fn merge_chunks(rags: &[Rag], results: Vec<Vec<RagChunk>>) -> Vec<RagChunk> {
+    let mut scored: Vec<(f32, RagChunk)> = rags
+        .iter()
+        .zip(results)
+        .flat_map(|(rag, chunks)| {
+            chunks
+                .into_iter()
+                .map(move |chunk| (rag.rag_weight / (1.0 + chunk.distance), chunk))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, chunk)| chunk).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, distance: f32) -> RagChunk {
+        RagChunk {
+            id: id.to_string(),
+            document: String::new(),
+            distance,
+        }
+    }
+
+    #[test]
+    fn test_merge_chunks() {
+        let docs = Rag {
+            rag_weight: 1.0,
+            ..Default::default()
+        };
+        let code = Rag {
+            rag_weight: 2.0,
+            ..Default::default()
+        };
+        let results = vec![
+            vec![chunk("docs-1", 0.2), chunk("docs-2", 0.5)],
+            vec![chunk("code-1", 1.0), chunk("code-2", 3.0)],
+        ];
+        let merged = merge_chunks(&[docs, code], results);
+        let ids: Vec<&str> = merged.iter().map(|chunk| chunk.id.as_str()).collect();
+        // Scores: docs-1 0.83, docs-2 0.67, code-1 1.0, code-2 0.5
+        assert_eq!(ids, ["code-1", "docs-1", "docs-2", "code-2"]);
+    }
 }
@@ -0,0 +1,98 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{Context, Result};
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs};
+use fancy_regex::Regex;
+use serde::Deserialize;
+
+use super::{sampling::SamplingParams, TEMP_ROLE_NAME};
+
+lazy_static::lazy_static! {
+    static ref RE_FRONT_MATTER: Regex = Regex::new(r"(?s)^---\s*\n(.*?)\n---\s*(?:\n|$)").unwrap();
+}
+
+/// A system prompt persona, with no tools or RAGs unlike an agent
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Role {
+    #[serde(skip)]
+    pub name: String,
+    pub prompt: String,
+    /// Overrides the global model, as `model` or `provider:model`
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub sampling: SamplingParams,
+}
+
+impl Role {
+    /// A temporary role made of a prompt only
+    pub fn new(name: &str, prompt: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            prompt: prompt.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Load `<name>.md`, a prompt with optional YAML front matter, or `<name>.yaml`
+    pub fn init(name: &str, path: &Path) -> Result<Self> {
+        let content = read_to_string(path)
+            .with_context(|| format!("Failed to load role '{}' at '{}'", name, path.display()))?;
+        let markdown = path.extension().is_some_and(|ext| ext == "md");
+        Self::parse(name, &content, markdown).with_context(|| format!("Invalid role '{name}'"))
+    }
+
+    pub fn parse(name: &str, content: &str, markdown: bool) -> Result<Self> {
+        let mut role: Role = if markdown {
+            match RE_FRONT_MATTER.captures(content)? {
+                Some(captures) => {
+                    let mut role: Role = serde_yaml::from_str(&captures[1])?;
+                    role.prompt = content[captures[0].len()..].trim().to_string();
+                    role
+                }
+                None => Role::new(name, content.trim()),
+            }
+        } else {
+            serde_yaml::from_str(content)?
+        };
+        role.name = name.to_string();
+        Ok(role)
+    }
+
+    pub fn is_temp(&self) -> bool {
+        self.name == TEMP_ROLE_NAME
+    }
+
+    pub fn echo_system_message(&self) -> Result<Option<ChatCompletionRequestMessage>> {
+        if self.prompt.trim().is_empty() {
+            return Ok(None);
+        }
+        let message = ChatCompletionRequestSystemMessageArgs::default()
+            .content(self.prompt.clone())
+            .build()?;
+        Ok(Some(message.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "---\nmodel: openai:gpt-4o\ntemperature: 0.2\n---\nYou are a shell expert.\n";
+        let role = Role::parse("shell", content, true).unwrap();
+        assert_eq!(role.name, "shell");
+        assert_eq!(role.prompt, "You are a shell expert.");
+        assert_eq!(role.model.as_deref(), Some("openai:gpt-4o"));
+        assert_eq!(role.sampling.temperature, Some(0.2));
+
+        let role = Role::parse("plain", "Answer in French.", true).unwrap();
+        assert_eq!(role.prompt, "Answer in French.");
+        assert!(role.model.is_none());
+
+        let role = Role::parse("yaml", "prompt: Be brief.\ntop_p: 0.5\n", false).unwrap();
+        assert_eq!(role.prompt, "Be brief.");
+        assert_eq!(role.sampling.top_p, Some(0.5));
+    }
+}
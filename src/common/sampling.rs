@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+use async_openai::types::{CreateChatCompletionRequest, ResponseFormat, Stop};
+use serde::{Deserialize, Serialize};
+
+/// Keys accepted by `SamplingParams::set`
+pub const SAMPLING_KEYS: [&str; 6] = ["temperature", "top_p", "max_tokens", "seed", "stop", "response_format"];
+
+/// Sampling parameters of a request, unset ones fall back to the next level
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Stop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+impl SamplingParams {
+    /// Values of `self` win, missing ones come from `fallback`
+    pub fn or(&self, fallback: &SamplingParams) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            seed: self.seed.or(fallback.seed),
+            stop: self.stop.clone().or_else(|| fallback.stop.clone()),
+            response_format: self.response_format.clone().or_else(|| fallback.response_format.clone()),
+        }
+    }
+
+    /// Fill the parameters the request leaves unset
+    pub fn apply(&self, request: &mut CreateChatCompletionRequest) {
+        request.temperature = request.temperature.or(self.temperature);
+        request.top_p = request.top_p.or(self.top_p);
+        #[allow(deprecated)]
+        if request.max_tokens.is_none() && request.max_completion_tokens.is_none() {
+            request.max_tokens = self.max_tokens;
+        }
+        request.seed = request.seed.or(self.seed);
+        if request.stop.is_none() {
+            request.stop = self.stop.clone();
+        }
+        if request.response_format.is_none() {
+            request.response_format = self.response_format.clone();
+        }
+    }
+
+    /// Set a parameter from its text form, `null` unsets it
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = value.trim();
+        let unset = value == "null";
+        match key {
+            "temperature" => self.temperature = if unset { None } else { Some(value.parse()?) },
+            "top_p" => self.top_p = if unset { None } else { Some(value.parse()?) },
+            "max_tokens" => self.max_tokens = if unset { None } else { Some(value.parse()?) },
+            "seed" => self.seed = if unset { None } else { Some(value.parse()?) },
+            "stop" => {
+                self.stop = if unset {
+                    None
+                } else {
+                    Some(Stop::StringArray(value.split(',').map(|v| v.to_string()).collect()))
+                }
+            }
+            "response_format" => {
+                self.response_format = match value {
+                    "null" => None,
+                    "text" => Some(ResponseFormat::Text),
+                    "json_object" => Some(ResponseFormat::JsonObject),
+                    _ => Some(serde_json::from_str(value)?),
+                }
+            }
+            _ => bail!("Unknown key '{key}', expect one of {}", SAMPLING_KEYS.join(", ")),
+        }
+        Ok(())
+    }
+
+    pub fn items(&self) -> Vec<(&'static str, String)> {
+        let mut items = vec![];
+        if let Some(temperature) = self.temperature {
+            items.push(("temperature", temperature.to_string()));
+        }
+        if let Some(top_p) = self.top_p {
+            items.push(("top_p", top_p.to_string()));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            items.push(("max_tokens", max_tokens.to_string()));
+        }
+        if let Some(seed) = self.seed {
+            items.push(("seed", seed.to_string()));
+        }
+        if let Some(stop) = &self.stop {
+            items.push(("stop", serde_json::to_string(stop).unwrap_or_default()));
+        }
+        if let Some(response_format) = &self.response_format {
+            items.push(("response_format", serde_json::to_string(response_format).unwrap_or_default()));
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let mut global = SamplingParams::default();
+        global.set("temperature", "0.2").unwrap();
+        global.set("seed", "7").unwrap();
+        let mut session = SamplingParams::default();
+        session.set("temperature", "0.9").unwrap();
+        session.set("response_format", "json_object").unwrap();
+
+        let params = session.or(&global);
+        assert_eq!(params.temperature, Some(0.9));
+        assert_eq!(params.seed, Some(7));
+        assert_eq!(params.response_format, Some(ResponseFormat::JsonObject));
+
+        session.set("temperature", "null").unwrap();
+        assert_eq!(session.or(&global).temperature, Some(0.2));
+    }
+}
@@ -1,22 +1,44 @@
 use std::path::Path;
 
-use super::{ensure_parent_exists, input::Input, TEMP_SESSION_NAME};
-use anyhow::{Context, Result};
-use async_openai::types::{ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestUserMessageArgs};
+use super::{ensure_parent_exists, error::RagentsError, input::Input, sampling::SamplingParams, tokens::{estimate_messages_tokens, TokenUsage}, COMPRESS_KEEP_TURNS, SUMMARY_PROMPT, TEMP_SESSION_NAME};
+use anyhow::{bail, Context, Result};
+use async_openai::types::{CompletionUsage, ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs};
 use fancy_regex::Regex;
 use inquire::{validator::Validation, Confirm, Text};
 use std::fs::{read_to_string, write};
 use serde::{Deserialize, Serialize};
 
+/// Bumped whenever the layout of the session file changes
+const SESSION_VERSION: u32 = 1;
+
 lazy_static::lazy_static! {
-    static ref RE_AUTONAME_PREFIX: Regex = Regex::new(r"\d{8}T\d{6}-").unwrap();
+    static ref RE_AUTONAME_PREFIX: Regex = Regex::new(r"^\d{8}T\d{6}-").unwrap();
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Session {
+    #[serde(default)]
+    version: u32,
+    /// Messages replaced by a summary, kept for auditing but never sent again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    compressed_messages: Vec<ChatCompletionRequestMessage>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    // compressed_messages: Vec<Message>,
     messages: Vec<ChatCompletionRequestMessage>,
+    /// Role the session was held with, restored when the session is used again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(flatten)]
+    sampling: SamplingParams,
+    #[serde(default, skip_serializing_if = "TokenUsage::is_empty")]
+    usage: TokenUsage,
+    /// Slug title generated from the first exchange of a temporary session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    autoname: Option<String>,
+    #[serde(skip)]
+    autoname_tried: bool,
+    /// Tokens of the conversation as reported by the last request
+    #[serde(skip)]
+    context_tokens: Option<usize>,
     #[serde(skip)]
     pub name: String,
     #[serde(skip)]
@@ -41,18 +63,75 @@ impl Session {
         self.messages.is_empty()
     }
 
+    pub fn messages(&self) -> &[ChatCompletionRequestMessage] {
+        &self.messages
+    }
+
+    pub fn sampling(&self) -> &SamplingParams {
+        &self.sampling
+    }
+
+    pub fn set_sampling(&mut self, key: &str, value: &str) -> Result<()> {
+        self.sampling.set(key, value)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+
+    pub fn set_role(&mut self, role: Option<&str>) {
+        let role = role.map(|v| v.to_string());
+        if self.role != role {
+            self.role = role;
+            self.dirty = true;
+        }
+    }
+
+    pub fn usage(&self) -> &TokenUsage {
+        &self.usage
+    }
+
+    pub fn record_usage(&mut self, usage: &CompletionUsage) {
+        self.usage.add(usage);
+        self.context_tokens = Some(usage.total_tokens as usize);
+        self.dirty = true;
+    }
+
+    /// Tokens the conversation takes, and the share of `max_input_tokens` when known
+    pub fn tokens_usage(&self, max_input_tokens: Option<usize>) -> (usize, Option<f32>) {
+        let tokens = self
+            .context_tokens
+            .unwrap_or_else(|| estimate_messages_tokens(&self.messages));
+        let percent = max_input_tokens
+            .filter(|max| *max > 0)
+            .map(|max| tokens as f32 * 100.0 / max as f32);
+        (tokens, percent)
+    }
+
     pub fn load(name: &str, path: &Path) -> Result<Self> {
         let content = read_to_string(path)
             .with_context(|| format!("Failed to load session {} at {}", name, path.display()))?;
         let mut session: Session =
             serde_yaml::from_str(&content).with_context(|| format!("Invalid session {}", name))?;
+        if session.version > SESSION_VERSION {
+            bail!(RagentsError::Session(format!(
+                "Session {} has version {}, newer than the supported version {}",
+                name,
+                session.version,
+                SESSION_VERSION
+            )));
+        }
 
         // session.model = Model::retrieve_model(config, &session.model_id, ModelType::Chat)?;
         if let Some(autoname) = name.strip_prefix("_/") {
             session.name = TEMP_SESSION_NAME.to_string();
             session.path = None;
-            if let Ok(true) = RE_AUTONAME_PREFIX.is_match(autoname) {
-                // session.autoname = Some(AutoName::new(autoname[16..].to_string()));
+            if let Ok(Some(prefix)) = RE_AUTONAME_PREFIX.find(autoname) {
+                if session.autoname.is_none() {
+                    session.autoname = autoname.strip_prefix(prefix.as_str()).map(|v| v.to_string());
+                }
             }
         } else {
             session.name = name.to_string();
@@ -65,6 +144,7 @@ impl Session {
         ensure_parent_exists(session_path)?;
 
         self.path = Some(session_path.display().to_string());
+        self.version = SESSION_VERSION;
 
         let content = serde_yaml::to_string(&self)
             .with_context(|| format!("Failed to serde session '{}'", self.name))?;
@@ -103,6 +183,7 @@ impl Session {
                 }
                 if session_name == TEMP_SESSION_NAME {
                     session_name = Text::new("Session name:")
+                        .with_default(self.autoname.as_deref().unwrap_or_default())
                         .with_validator(|input: &str| {
                             let input = input.trim();
                             if input.is_empty() {
@@ -123,7 +204,7 @@ impl Session {
 
                 let now = chrono::Local::now();
                 session_name = now.format("%Y%m%dT%H%M%S").to_string();
-                if let Some(autoname) = Some("32423532") {
+                if let Some(autoname) = &self.autoname {
                     session_name = format!("{session_name}-{autoname}")
                 }
             }
@@ -133,32 +214,271 @@ impl Session {
         Ok(())
     }
 
-    pub fn add_message(&mut self, input: &Input, output: &str) -> Result<()> {
-        self.messages.push(ChatCompletionRequestAssistantMessageArgs::default().content(output).build()?.into());
+    pub fn add_message(&mut self, input: &Input, tool_messages: &[ChatCompletionRequestMessage], output: &str) -> Result<()> {
+        if input.replaces_last_turn() {
+            self.truncate_last_turn();
+        }
+        self.messages.push(ChatCompletionRequestUserMessageArgs::default().content(input.text.as_str()).build()?.into());
+        self.messages.extend(tool_messages.iter().cloned());
+        if !output.is_empty() {
+            self.messages.push(ChatCompletionRequestAssistantMessageArgs::default().content(output).build()?.into());
+        }
+        self.context_tokens = None;
         self.dirty = true;
         Ok(())
     }
 
     pub fn echo_messages(&self, input: &Input) -> Result<Vec<ChatCompletionRequestMessage>> {
-        let messages = self.build_messages(input);
+        let messages = self.build_messages(input)?;
         Ok(messages)
     }
 
-    pub fn build_messages(&self, input: &Input) -> Vec<ChatCompletionRequestMessage> {
+    pub fn build_messages(&self, input: &Input) -> Result<Vec<ChatCompletionRequestMessage>> {
         let mut messages = self.messages.clone();
-        if input.regenerate() {
-            messages.pop();
-            return messages;
+        if input.replaces_last_turn() {
+            if let Some(index) = last_user_index(&messages) {
+                messages.truncate(index);
+            }
+        }
+        messages.push(ChatCompletionRequestUserMessageArgs::default().content(input.message_content()).build()?.into());
+        if let Some(output) = input.continue_output() {
+            messages.push(ChatCompletionRequestAssistantMessageArgs::default().content(output).build()?.into());
+        }
+        Ok(messages)
+    }
+
+    /// Drop the last user message and everything answered after it
+    fn truncate_last_turn(&mut self) {
+        if let Some(index) = last_user_index(&self.messages) {
+            self.messages.truncate(index);
         }
+    }
+
+    /// The messages before the last `COMPRESS_KEEP_TURNS` user turns, empty when there are no more turns
+    pub fn messages_to_compress(&self) -> &[ChatCompletionRequestMessage] {
+        let split = self
+            .messages
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, message)| matches!(message, ChatCompletionRequestMessage::User(_)))
+            .nth(COMPRESS_KEEP_TURNS - 1)
+            .map(|(index, _)| index)
+            .unwrap_or_default();
+        &self.messages[..split]
+    }
+
+    /// Archive the first `count` messages and put `summary` in their place
+    pub fn compress(&mut self, summary: &str, count: usize) -> Result<()> {
+        let summary_message = ChatCompletionRequestSystemMessageArgs::default()
+            .content(format!("{SUMMARY_PROMPT}{summary}"))
+            .build()?;
+        let count = count.min(self.messages.len());
+        self.compressed_messages.extend(self.messages.drain(..count));
+        self.messages.insert(0, summary_message.into());
+        self.context_tokens = None;
+        self.dirty = true;
+        Ok(())
+    }
 
-        messages.push(ChatCompletionRequestUserMessageArgs::default().content(input.message_content()).build().unwrap().into());
-        messages
+    pub fn autoname(&self) -> Option<&str> {
+        self.autoname.as_deref()
+    }
+
+    pub fn set_autoname(&mut self, title: &str) {
+        let slug = slugify(title);
+        if !slug.is_empty() {
+            self.autoname = Some(slug);
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_save_session(&mut self, save_session: Option<bool>) {
+        self.save_session = save_session;
+    }
+
+    /// The first exchange of a temporary session still without a name, only offered once
+    pub fn take_autoname_messages(&mut self) -> Option<Vec<ChatCompletionRequestMessage>> {
+        if self.name != TEMP_SESSION_NAME || self.autoname.is_some() || self.autoname_tried {
+            return None;
+        }
+        let start = self
+            .messages
+            .iter()
+            .position(|message| matches!(message, ChatCompletionRequestMessage::User(_)))?;
+        // Tool calls and their results are left out, a call without its results is rejected upstream
+        let answer = self.messages[start..].iter().find_map(|message| match message {
+            ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+                content: Some(ChatCompletionRequestAssistantMessageContent::Text(text)),
+                ..
+            }) if !text.trim().is_empty() => Some(text.clone()),
+            _ => None,
+        })?;
+        let answer = ChatCompletionRequestAssistantMessageArgs::default().content(answer).build().ok()?;
+        self.autoname_tried = true;
+        Some(vec![self.messages[start].clone(), answer.into()])
+    }
+
+    pub fn compressed_messages(&self) -> &[ChatCompletionRequestMessage] {
+        &self.compressed_messages
     }
 
     pub fn clear_messages(&mut self) {
         self.messages.clear();
+        self.context_tokens = None;
         self.dirty = true;
     }
 
 }
 
+/// Lowercase words joined by `-`, at most 48 chars
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for word in text
+        .to_lowercase()
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if slug.len() + word.len() + 1 > 48 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(word);
+    }
+    slug
+}
+
+fn last_user_index(messages: &[ChatCompletionRequestMessage]) -> Option<usize> {
+    messages
+        .iter()
+        .rposition(|message| matches!(message, ChatCompletionRequestMessage::User(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::CConfig;
+    use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionRequestToolMessageArgs, ChatCompletionToolType, FunctionCall};
+
+    /// A `fs_ls` call and its result
+    fn tool_messages() -> Vec<ChatCompletionRequestMessage> {
+        let tool_call = ChatCompletionMessageToolCall {
+            id: "call_0".into(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: "fs_ls".into(),
+                arguments: r#"{"path":"."}"#.into(),
+            },
+        };
+        vec![
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(vec![tool_call])
+                .build()
+                .unwrap()
+                .into(),
+            ChatCompletionRequestToolMessageArgs::default()
+                .content("[]")
+                .tool_call_id("call_0")
+                .build()
+                .unwrap()
+                .into(),
+        ]
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut session = Session::new("test");
+        session
+            .add_message(&Input::from_str("list files"), &tool_messages(), "No files.")
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("ragents-session-{}.yaml", std::process::id()));
+        session.save("test", &path, false).unwrap();
+        let loaded = Session::load("test", &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.version, SESSION_VERSION);
+        assert_eq!(loaded.messages, session.messages);
+        assert_eq!(loaded.messages.len(), 4);
+    }
+
+    #[test]
+    fn test_continue_then_regenerate() {
+        let mut session = Session::new("test");
+        session.add_message(&Input::from_str("tell a story"), &[], "Once").unwrap();
+        let mut input = Input::from_str("tell a story");
+        input.set_continue_output("Once");
+        session.add_message(&input, &[], "Once upon a time").unwrap();
+        assert_eq!(session.messages().len(), 2);
+
+        input.set_regenerate();
+        let messages = session.build_messages(&input).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], ChatCompletionRequestMessage::User(_)));
+        session.add_message(&input, &[], "A new story").unwrap();
+        let answer = ChatCompletionRequestAssistantMessageArgs::default().content("A new story").build().unwrap();
+        assert_eq!(session.messages().len(), 2);
+        assert_eq!(session.messages()[1], answer.into());
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut session = Session::new("test");
+        for (question, answer) in [("one", "1"), ("two", "2"), ("three", "3")] {
+            session.add_message(&Input::from_str(question), &[], answer).unwrap();
+        }
+        let mut config: CConfig = serde_yaml::from_str("model: mock\nagents: {}\ntools: {}\nrags: {}\n").unwrap();
+        config.session = Some(session);
+        assert!(config.session_to_compress().is_none());
+        config.compress_threshold = Some(1_000_000);
+        assert!(config.session_to_compress().is_none());
+        config.compress_threshold = Some(1);
+        let messages = config.session_to_compress().unwrap();
+        assert_eq!(messages.len(), 2);
+
+        config.compress_session("counting", messages.len()).unwrap();
+        let session = config.session.as_ref().unwrap();
+        assert_eq!(session.compressed_messages().len(), 2);
+        assert_eq!(session.messages().len(), 5);
+        assert!(matches!(session.messages()[0], ChatCompletionRequestMessage::System(_)));
+        assert!(matches!(session.messages()[1], ChatCompletionRequestMessage::User(_)));
+        assert_eq!(session.messages_to_compress().len(), 1);
+    }
+
+    #[test]
+    fn test_autoname() {
+        let mut session = Session::new(TEMP_SESSION_NAME);
+        session
+            .add_message(&Input::from_str("how do lifetimes work"), &tool_messages(), "They ...")
+            .unwrap();
+        let messages = session.take_autoname_messages().unwrap();
+        let answer = ChatCompletionRequestAssistantMessageArgs::default().content("They ...").build().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], ChatCompletionRequestMessage::User(_)));
+        assert_eq!(messages[1], answer.into());
+        assert!(session.take_autoname_messages().is_none());
+        session.set_autoname("Rust Lifetimes, Explained!");
+        assert_eq!(session.autoname(), Some("rust-lifetimes-explained"));
+
+        let dir = std::env::temp_dir().join(format!("ragents-sessions-{}", std::process::id()));
+        session.set_save_session(Some(true));
+        session.exit(&dir, false).unwrap();
+        let name = format!("_/{}", session.name);
+        let loaded = Session::load(&name, &dir.join(format!("{name}.yaml"))).unwrap();
+        assert!(name.ends_with("-rust-lifetimes-explained"));
+        assert_eq!(loaded.autoname(), Some("rust-lifetimes-explained"));
+
+        for (name, autoname) in [
+            ("_/20250101T000000-notes", Some("notes")),
+            ("_/aaaaaaaaaaaaaaa日20250101T000000-x", None),
+            ("_/draft-20250101T000000-x", None),
+        ] {
+            let path = dir.join(format!("{name}.yaml"));
+            std::fs::write(&path, "messages: []\n").unwrap();
+            assert_eq!(Session::load(name, &path).unwrap().autoname(), autoname);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
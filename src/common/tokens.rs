@@ -0,0 +1,97 @@
+use async_openai::types::{ChatCompletionRequestMessage, CompletionUsage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Tokens every message costs besides its content
+const MESSAGE_OVERHEAD_TOKENS: usize = 3;
+
+/// Usage summed over the requests of a session
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn is_empty(&self) -> bool {
+        self.total_tokens == 0
+    }
+
+    pub fn add(&mut self, usage: &CompletionUsage) {
+        self.prompt_tokens += usage.prompt_tokens as u64;
+        self.completion_tokens += usage.completion_tokens as u64;
+        self.total_tokens += usage.total_tokens as u64;
+    }
+}
+
+/// Usage of every request behind one answer, `None` when the upstream reported none
+pub fn sum_usages(usages: &[CompletionUsage]) -> Option<CompletionUsage> {
+    if usages.is_empty() {
+        return None;
+    }
+    let mut total = TokenUsage::default();
+    for usage in usages {
+        total.add(usage);
+    }
+    Some(CompletionUsage {
+        prompt_tokens: total.prompt_tokens as u32,
+        completion_tokens: total.completion_tokens as u32,
+        total_tokens: total.total_tokens as u32,
+        prompt_tokens_details: None,
+        completion_tokens_details: None,
+    })
+}
+
+/// A rough count for when the upstream reports no usage, about 4 ASCII chars or 1 other char per token
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(|ch| ch.is_ascii()).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+pub fn estimate_messages_tokens(messages: &[ChatCompletionRequestMessage]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            let value = serde_json::to_value(message).unwrap_or_default();
+            MESSAGE_OVERHEAD_TOKENS + estimate_value_tokens(&value)
+        })
+        .sum()
+}
+
+fn estimate_value_tokens(value: &Value) -> usize {
+    match value {
+        Value::String(text) => estimate_tokens(text),
+        Value::Array(values) => values.iter().map(estimate_value_tokens).sum(),
+        Value::Object(map) => map.values().map(estimate_value_tokens).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("Hello world"), 3);
+        assert_eq!(estimate_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn test_sum_usages() {
+        assert!(sum_usages(&[]).is_none());
+        let step = |prompt_tokens, completion_tokens| CompletionUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            prompt_tokens_details: None,
+            completion_tokens_details: None,
+        };
+        let usage = sum_usages(&[step(40, 8), step(60, 2)]).unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (100, 10, 110));
+    }
+}
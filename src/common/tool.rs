@@ -1,11 +1,18 @@
 use std::fs::read_to_string;
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
+use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 
 use async_openai::types::ChatCompletionTool;
+use futures::future::try_join_all;
 use serde::Deserialize;
+use serde_json::Value;
+
+use super::{db::db::create_database, error::RagentsError};
 
 #[derive(Default, Deserialize, Clone)]
 pub struct ToolInstance {
@@ -24,10 +31,94 @@ impl ToolInstance {
     }
 }
 
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(default)]
+pub struct ToolSandbox {
+    /// Seconds before the process is killed
+    pub timeout: Option<u64>,
+    /// Working directory of the process, path arguments resolving outside of it are refused
+    pub cwd: Option<String>,
+    /// Environment variables passed through; all are inherited when unset
+    pub env: Option<Vec<String>>,
+    /// Maximum bytes read from stdout before the process is killed, stderr is cut at the same size
+    pub max_output: Option<usize>,
+    pub rlimits: Option<ToolRlimits>,
+}
+
+impl ToolSandbox {
+    /// The canonical `cwd`, relative to the current directory
+    pub fn working_dir(&self) -> Result<Option<PathBuf>> {
+        let cwd = match &self.cwd {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let dir = Path::new(cwd)
+            .canonicalize()
+            .with_context(|| format!("Invalid tool working directory '{cwd}'"))?;
+        Ok(Some(dir))
+    }
+}
+
+/// Refuse string arguments naming a path outside of `dir`, following the symlinks of existing paths
+pub fn check_path_args(value: &Value, dir: &Path) -> Result<()> {
+    match value {
+        Value::String(text) => {
+            let path = dir.join(text);
+            let path = path.canonicalize().unwrap_or_else(|_| normalize_path(&path));
+            if !path.starts_with(dir) {
+                bail!(RagentsError::Tool(format!("'{text}' is outside of the tool's working directory")));
+            }
+        }
+        Value::Array(items) => items.iter().try_for_each(|item| check_path_args(item, dir))?,
+        Value::Object(map) => map.values().try_for_each(|item| check_path_args(item, dir))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Resolve `.` and `..` without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[derive(Debug, Default, Deserialize, Clone)]
+#[serde(default)]
+pub struct ToolRlimits {
+    /// CPU time in seconds
+    pub cpu: Option<u64>,
+    /// Address space in bytes
+    pub memory: Option<u64>,
+    /// Number of open file descriptors
+    pub nofile: Option<u64>,
+}
+
 #[derive(Debug, Default, Deserialize, Clone)]
 pub struct Tool {
+    #[serde(skip)]
+    pub name: String,
     pub tool_json: HashMap<String, String>,
     pub tool_exec: HashMap<String, String>,
+    #[serde(default)]
+    pub sandbox: ToolSandbox,
+    /// Ask the user before running any function of this tool
+    #[serde(default)]
+    pub confirm: bool,
+    /// Per-function override of `confirm`
+    #[serde(default)]
+    pub tool_confirm: HashMap<String, bool>,
+    /// Embedding model of the tool collection built by the `chromadb` binary
+    #[serde(default)]
+    pub tool_embedding_model: Option<String>,
     #[serde(skip)]
     pub tool: Vec<ChatCompletionTool>,
 }
@@ -36,7 +127,8 @@ impl Tool {
     pub fn init(tool_name: &str, tool_path: &str) -> Result<Self> {
         let err = || format!("Failed to load config at '{}'", tool_name);
         let content = read_to_string(tool_path).with_context(err)?;
-        let tool = serde_yaml::from_str(&content)?;
+        let mut tool: Self = serde_yaml::from_str(&content)?;
+        tool.name = tool_name.to_string();
         Ok(tool)
     }
 
@@ -48,11 +140,103 @@ impl Tool {
             let config: ChatCompletionTool = serde_json::from_str(&content)?;
             result.insert(tool_name.to_string(), config);
         }
-        self.tool = result.iter().map(|(_, tool)| tool.clone()).collect();
+        self.tool = result.values().cloned().collect();
         Ok(result)
     }
 
     pub fn tool_exec(&self, tool_name: &str) -> Option<&str> {
         self.tool_exec.get(tool_name).map(|x| x.as_str())
     }
+
+    pub async fn search(&self, query: &str, top_k: usize) -> Result<Vec<(String, f32)>> {
+        let database = create_database("chromadb");
+        database
+            .query_tool(&self.name, query, top_k, self.tool_embedding_model.as_deref())
+            .await
+            .map_err(|err| anyhow!("Failed to query tool '{}': {err}", self.name))
+    }
+
+    pub fn need_confirm(&self, tool_name: &str) -> bool {
+        self.tool_confirm.get(tool_name).copied().unwrap_or(self.confirm)
+    }
+}
+
+/// Query the tool collections concurrently and keep the `top_k` closest function definitions
+pub async fn search_tools(tools: &[Tool], query: &str, top_k: usize) -> Result<Vec<ChatCompletionTool>> {
+    let results = try_join_all(tools.iter().map(|tool| tool.search(query, top_k))).await?;
+    Ok(rank_tools(tools, results.into_iter().flatten().collect(), top_k))
+}
+
+/// The definitions of the `top_k` closest matches, names the tools don't define are skipped
+fn rank_tools(tools: &[Tool], mut matches: Vec<(String, f32)>, top_k: usize) -> Vec<ChatCompletionTool> {
+    matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+    matches
+        .iter()
+        .filter_map(|(name, _)| {
+            tools
+                .iter()
+                .flat_map(|tool| tool.tool.iter())
+                .find(|tool| &tool.function.name == name)
+                .cloned()
+        })
+        .take(top_k)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_openai::types::{ChatCompletionToolType, FunctionObject};
+
+    use super::*;
+
+    fn function(name: &str) -> ChatCompletionTool {
+        ChatCompletionTool {
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionObject {
+                name: name.to_string(),
+                description: None,
+                parameters: None,
+                strict: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_rank_tools() {
+        let web = Tool {
+            tool: vec![function("get_current_time"), function("get_current_weather")],
+            ..Default::default()
+        };
+        let fso = Tool {
+            tool: vec![function("fs_ls"), function("fs_cat")],
+            ..Default::default()
+        };
+        let matches = vec![
+            ("fs_cat".to_string(), 0.7),
+            ("get_current_weather".to_string(), 0.1),
+            ("removed_function".to_string(), 0.2),
+            ("fs_ls".to_string(), 0.3),
+        ];
+        let ranked = rank_tools(&[web, fso], matches, 2);
+        let names: Vec<&str> = ranked.iter().map(|tool| tool.function.name.as_str()).collect();
+        assert_eq!(names, ["get_current_weather", "fs_ls"]);
+    }
+
+    #[test]
+    fn test_check_path_args() {
+        let dir = std::env::temp_dir().join(format!("ragents-tool-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let args = serde_json::json!({"file": ["src/main.rs", "./README.md", "src/../Cargo.toml"], "number_lines": true});
+        assert!(check_path_args(&args, &dir).is_ok());
+        assert!(check_path_args(&serde_json::json!({"path": dir.join("src")}), &dir).is_ok());
+        assert!(check_path_args(&serde_json::json!({"file": ["src/../../secret"]}), &dir).is_err());
+        assert!(check_path_args(&serde_json::json!({"path": "/etc/passwd"}), &dir).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+            assert!(check_path_args(&serde_json::json!({"path": "etc/passwd"}), &dir).is_err());
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
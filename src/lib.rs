@@ -10,6 +10,18 @@ pub struct CliMain {
     pub mode: Mode,
 }
 
+/// Loads the tool and RAG collections into Chroma
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct CliChromadb {
+    /// Config file whose `tools` are embedded, e.g. config.yaml
+    #[arg(short, long)]
+    pub tools: String,
+    /// Config file whose `rags` are embedded, e.g. config.yaml
+    #[arg(short, long)]
+    pub rags: String,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Mode {
     /// Start the program in Realtime mode
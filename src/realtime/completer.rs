@@ -0,0 +1,234 @@
+use std::{fs::read_dir, path::PathBuf};
+
+use reedline::{Completer, Span, Suggestion};
+
+use crate::common::{config::Config, expand_path, sampling::SAMPLING_KEYS, DEFAULT_CLIENT_NAME};
+
+use super::{parse_command, REALTIME_COMMANDS};
+
+const LIST_KINDS: [&str; 5] = ["session", "role", "rag", "agent", "tool"];
+
+pub struct RealtimeCompleter {
+    config: Config,
+}
+
+impl RealtimeCompleter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Values for the argument of `cmd`, `args` is what precedes the word being typed
+    fn argument_candidates(&self, cmd: &str, args: &[&str]) -> Vec<(String, Option<String>)> {
+        let config = self.config.read();
+        let names = |names: Vec<String>| names.into_iter().map(|v| (v, None)).collect();
+        let rows = |rows: Vec<(String, String)>| {
+            rows.into_iter()
+                .map(|(name, info)| (name, Some(info).filter(|v| !v.is_empty())))
+                .collect()
+        };
+        match (cmd, args) {
+            (".agent", []) => rows(config.list_agents()),
+            (".agent", [agent_name]) => names(config.list_agent_sessions(agent_name)),
+            (".role", []) => rows(config.list_roles()),
+            (".rag", []) => rows(config.list_rags()),
+            (".session", []) | (".save", ["session"]) => names(config.list_sessions()),
+            (".list", []) => LIST_KINDS
+                .iter()
+                .map(|kind| {
+                    let mut items = match *kind {
+                        "session" => config.list_sessions(),
+                        "role" => config.list_role_names(),
+                        "rag" => config.rags.keys().cloned().collect(),
+                        "agent" => config.agents.keys().cloned().collect(),
+                        _ => config.tools.keys().cloned().collect(),
+                    };
+                    items.sort();
+                    (kind.to_string(), Some(items.join(", ")).filter(|v| !v.is_empty()))
+                })
+                .collect(),
+            (".set", []) => names(SAMPLING_KEYS.iter().map(|v| v.to_string()).collect()),
+            (".model", []) => {
+                let mut models = vec![];
+                for client in config.client_configs() {
+                    for model in client.models {
+                        if client.name == DEFAULT_CLIENT_NAME {
+                            models.push(model.name);
+                        } else {
+                            models.push(format!("{}:{}", client.name, model.name));
+                        }
+                    }
+                }
+                names(models)
+            }
+            _ => vec![],
+        }
+    }
+}
+
+impl Completer for RealtimeCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        let line = &line[..pos];
+        let word = line.rsplit(char::is_whitespace).next().unwrap_or_default();
+        let span = Span::new(pos - word.len(), pos);
+        let (cmd, args) = match parse_command(line) {
+            Some(v) => v,
+            None => return vec![],
+        };
+        if let Some(suggestions) = complete_command(line, pos) {
+            return suggestions;
+        }
+        let args: Vec<&str> = args.unwrap_or_default().split_whitespace().collect();
+        let args = match word.is_empty() {
+            true => &args[..],
+            false => &args[..args.len().saturating_sub(1)],
+        };
+        if cmd == ".file" && !args.contains(&"--") {
+            return complete_path(word, span);
+        }
+        self.argument_candidates(cmd, args)
+            .into_iter()
+            .filter(|(value, _)| value.starts_with(word))
+            .map(|(value, description)| Suggestion {
+                value,
+                description,
+                span,
+                append_whitespace: true,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// Commands matching `line`, or `None` once past the command name
+fn complete_command(line: &str, pos: usize) -> Option<Vec<Suggestion>> {
+    let start = line.len() - line.trim_start().len();
+    let typed = &line[start..];
+    let mut matched = false;
+    let mut suggestions = vec![];
+    for cmd in REALTIME_COMMANDS.iter() {
+        if cmd.name.starts_with(typed) && cmd.name != typed {
+            matched = true;
+            suggestions.push(Suggestion {
+                value: cmd.name.to_string(),
+                description: Some(cmd.description.to_string()),
+                span: Span::new(start, pos),
+                append_whitespace: true,
+                ..Default::default()
+            });
+        }
+    }
+    if !matched && typed.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(suggestions)
+}
+
+/// Entries of the directory in `word` whose name starts with its last component
+fn complete_path(word: &str, span: Span) -> Vec<Suggestion> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(index) => (&word[..index + 1], &word[index + 1..]),
+        None => ("", word),
+    };
+    let search_dir = match dir.is_empty() {
+        true => PathBuf::from("."),
+        false => expand_path(dir),
+    };
+    let entries = match read_dir(search_dir) {
+        Ok(v) => v,
+        Err(_) => return vec![],
+    };
+    let mut suggestions: Vec<Suggestion> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            let suffix = if is_dir { "/" } else { "" };
+            Some(Suggestion {
+                value: format!("{dir}{name}{suffix}"),
+                span,
+                append_whitespace: !is_dir,
+                ..Default::default()
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| a.value.cmp(&b.value));
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+
+    use super::*;
+    use crate::common::config::CConfig;
+
+    fn values(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|v| v.value.as_str()).collect()
+    }
+
+    #[test]
+    fn test_complete_command() {
+        let line = ".exit s";
+        let suggestions = complete_command(line, line.len()).unwrap();
+        assert_eq!(values(&suggestions), [".exit session"]);
+        assert_eq!(suggestions[0].span, Span::new(0, line.len()));
+
+        let line = ".se";
+        assert_eq!(values(&complete_command(line, line.len()).unwrap()), [".set", ".session"]);
+
+        let line = ".agent co";
+        assert!(complete_command(line, line.len()).is_none());
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let dir = std::env::temp_dir().join(format!("ragents-complete-path-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("notes")).unwrap();
+        std::fs::write(dir.join("note.md"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let config: CConfig = serde_yaml::from_str("model: mock\nagents: {}\ntools: {}\nrags: {}\n").unwrap();
+        let mut completer = RealtimeCompleter::new(&Arc::new(RwLock::new(config)));
+
+        let line = format!(".file {}/no", dir.display());
+        let suggestions = completer.complete(&line, line.len());
+        let expect = [format!("{}/note.md", dir.display()), format!("{}/notes/", dir.display())];
+        assert_eq!(values(&suggestions), expect);
+        assert!(!suggestions[1].append_whitespace);
+
+        let line = format!(".file {0}/note.md {0}/", dir.display());
+        assert_eq!(completer.complete(&line, line.len()).len(), 2);
+        let line = format!(".file {}/note.md -- no", dir.display());
+        assert!(completer.complete(&line, line.len()).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_agent_sessions() {
+        let dir = std::env::temp_dir().join(format!("ragents-completer-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sessions/_")).unwrap();
+        std::fs::write(dir.join("sessions/review.yaml"), "").unwrap();
+        std::fs::write(dir.join("sessions/_/20250101T000000-notes.yaml"), "").unwrap();
+        std::env::set_var("COMPLETER_DEMO_DATA_DIR", &dir);
+        let config: CConfig =
+            serde_yaml::from_str("model: mock\nagents: {completer-demo: x}\ntools: {}\nrags: {}\n").unwrap();
+        let mut completer = RealtimeCompleter::new(&Arc::new(RwLock::new(config)));
+
+        let line = ".agent completer-demo ";
+        let expect = ["_/20250101T000000-notes", "review"];
+        assert_eq!(values(&completer.complete(line, line.len())), expect);
+        let line = ".agent completer-demo re";
+        assert_eq!(values(&completer.complete(line, line.len())), ["review"]);
+        let line = ".agent unknown ";
+        assert!(completer.complete(line, line.len()).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
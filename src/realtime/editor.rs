@@ -1,7 +1,9 @@
 use reedline::{default_emacs_keybindings, ColumnarMenu, EditCommand, EditMode, Emacs, KeyCode, KeyModifiers, Keybindings, MenuBuilder, Reedline, ReedlineEvent, ReedlineMenu};
 use anyhow::Result;
 
-use super::highlighter::RealtimeHighlighter;
+use crate::common::config::Config;
+
+use super::{completer::RealtimeCompleter, highlighter::RealtimeHighlighter, history::RealtimeHistory};
 
 fn extra_keybindings(keybindings: &mut Keybindings) {
     keybindings.add_binding(
@@ -34,7 +36,8 @@ fn extra_keybindings(keybindings: &mut Keybindings) {
     );
 }
 
-pub fn create_editor() -> Result<Reedline> {
+pub fn create_editor(config: &Config) -> Result<Reedline> {
+    let completer = RealtimeCompleter::new(config);
     let highlighter = RealtimeHighlighter::new();
     let menu = {
         let completion_menu = ColumnarMenu::default().with_name("completion_menu");
@@ -46,7 +49,8 @@ pub fn create_editor() -> Result<Reedline> {
         extra_keybindings(&mut keybindings);
         Box::new(Emacs::new(keybindings))
     };
-    let editor = Reedline::create()
+    let mut editor = Reedline::create()
+        .with_completer(Box::new(completer))
         .with_highlighter(Box::new(highlighter))
         .with_menu(menu)
         .with_edit_mode(edit_mode)
@@ -54,6 +58,9 @@ pub fn create_editor() -> Result<Reedline> {
         .with_partial_completions(true)
         .use_bracketed_paste(true)
         .with_ansi_colors(true);
+    if config.read().history_size() > 0 {
+        editor = editor.with_history(Box::new(RealtimeHistory::new(config)?));
+    }
 
     Ok(editor)
 }
\ No newline at end of file
@@ -7,6 +7,7 @@ use super::REALTIME_COMMANDS;
 const DEFAULT_COLOR: Color = Color::Default;
 const MATCH_COLOR: Color = Color::Green;
 
+#[derive(Default)]
 pub struct RealtimeHighlighter;
 
 impl RealtimeHighlighter {
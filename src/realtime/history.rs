@@ -0,0 +1,176 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use reedline::{
+    FileBackedHistory, History, HistoryItem, HistoryItemId, HistorySessionId, Result, SearchDirection, SearchQuery,
+};
+
+use crate::common::config::Config;
+
+/// File-backed history without older duplicates or lines typed after a space, following the agent's own file
+pub struct RealtimeHistory {
+    config: Config,
+    file: PathBuf,
+    inner: FileBackedHistory,
+}
+
+impl RealtimeHistory {
+    pub fn new(config: &Config) -> Result<Self> {
+        let (capacity, file) = {
+            let config = config.read();
+            (config.history_size(), config.history_file())
+        };
+        Ok(Self {
+            config: config.clone(),
+            inner: FileBackedHistory::with_file(capacity, file.clone())?,
+            file,
+        })
+    }
+
+    /// Open the history of the agent in use when it has its own
+    fn follow_agent(&mut self) -> Result<()> {
+        let (capacity, file) = {
+            let config = self.config.read();
+            (config.history_size(), config.history_file())
+        };
+        if file != self.file {
+            self.inner = FileBackedHistory::with_file(capacity, file.clone())?;
+            self.file = file;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the history without the older copies of repeated lines, whether it changed
+    fn compact(&mut self) -> Result<bool> {
+        let lines: Vec<String> = self
+            .inner
+            .search(SearchQuery::everything(SearchDirection::Forward, None))?
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        let mut seen = HashSet::new();
+        let mut kept: Vec<&String> = lines.iter().rev().filter(|line| seen.insert(line.as_str())).collect();
+        if kept.len() == lines.len() {
+            return Ok(false);
+        }
+        kept.reverse();
+        // FileBackedHistory can't delete single entries
+        self.inner.clear()?;
+        for line in kept {
+            self.inner.save(HistoryItem::from_command_line(line))?;
+        }
+        Ok(true)
+    }
+}
+
+impl History for RealtimeHistory {
+    fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
+        if h.command_line.starts_with(' ') {
+            return Ok(HistoryItem { id: None, ..h });
+        }
+        self.inner.save(h)
+    }
+
+    fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+        self.inner.load(id)
+    }
+
+    fn count(&self, query: SearchQuery) -> Result<i64> {
+        self.inner.count(query)
+    }
+
+    fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+        self.inner.search(query)
+    }
+
+    fn update(&mut self, id: HistoryItemId, updater: &dyn Fn(HistoryItem) -> HistoryItem) -> Result<()> {
+        self.inner.update(id, updater)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.inner.clear()
+    }
+
+    fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+        self.inner.delete(h)
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.inner.sync()?;
+        self.follow_agent().map_err(std::io::Error::other)?;
+        if self.compact().map_err(std::io::Error::other)? {
+            self.inner.sync()?;
+        }
+        Ok(())
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        self.inner.session()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::read_to_string, path::Path, sync::Arc};
+
+    use parking_lot::RwLock;
+
+    use super::*;
+    use crate::common::{agent::Agent, config::CConfig};
+
+    /// An agent keeping its history in `dir`, out of the user's config dir
+    fn agent(name: &str, dir: &Path) -> Option<Agent> {
+        std::env::set_var(format!("{}_DATA_DIR", name.replace('-', "_").to_ascii_uppercase()), dir);
+        Some(Agent {
+            name: name.into(),
+            ..Default::default()
+        })
+    }
+
+    fn history(agent: Option<Agent>) -> RealtimeHistory {
+        let mut config: CConfig =
+            serde_yaml::from_str("model: mock\nagents: {}\ntools: {}\nrags: {}\nagent_history: true\n").unwrap();
+        config.agent = agent;
+        RealtimeHistory::new(&Arc::new(RwLock::new(config))).unwrap()
+    }
+
+    #[test]
+    fn test_sync_dedup() {
+        let dir = std::env::temp_dir().join(format!("ragents-history-{}", std::process::id()));
+        let mut history = history(agent("history-dedup", &dir));
+        for line in ["hi", ".info", " secret", "hi", ".agent demo"] {
+            history.save(HistoryItem::from_command_line(line)).unwrap();
+        }
+        history.sync().unwrap();
+        let lines: Vec<String> = history
+            .search(SearchQuery::everything(SearchDirection::Backward, None))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        assert_eq!(lines, [".agent demo", "hi", ".info"]);
+        assert_eq!(read_to_string(dir.join("history.txt")).unwrap(), ".info\nhi\n.agent demo\n");
+
+        drop(history);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_follow_agent() {
+        let dir = std::env::temp_dir().join(format!("ragents-history-agent-{}", std::process::id()));
+        let mut history = history(agent("history-first", &dir.join("first")));
+        history.save(HistoryItem::from_command_line("hi")).unwrap();
+        history.sync().unwrap();
+
+        history.config.write().agent = agent("history-second", &dir.join("second"));
+        history.sync().unwrap();
+        assert_eq!(history.file, dir.join("second").join("history.txt"));
+        assert_eq!(history.count(SearchQuery::everything(SearchDirection::Forward, None)).unwrap(), 0);
+        history.save(HistoryItem::from_command_line("in second")).unwrap();
+        history.sync().unwrap();
+        assert_eq!(read_to_string(dir.join("second").join("history.txt")).unwrap(), "in second\n");
+        assert_eq!(read_to_string(dir.join("first").join("history.txt")).unwrap(), "hi\n");
+
+        drop(history);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
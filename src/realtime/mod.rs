@@ -2,23 +2,33 @@ use fancy_regex::Regex;
 use realtime::RealtimeCommand;
 use anyhow::{bail, Result};
 
+#[allow(clippy::module_inception)]
 pub mod realtime;
 pub mod abort;
 pub mod editor;
+pub mod completer;
+pub mod history;
 pub mod prompt;
 pub mod highlighter;
+pub mod render;
 
 lazy_static::lazy_static! {
-    static ref REALTIME_COMMANDS: [RealtimeCommand; 15] = [
+    static ref REALTIME_COMMANDS: [RealtimeCommand; 19] = [
         RealtimeCommand::new(".help", "Show this help message"),
         RealtimeCommand::new(".info", "View system info"),
+        RealtimeCommand::new(".model", "Change the current LLM"),
+        RealtimeCommand::new(".set", "Adjust a sampling parameter"),
         RealtimeCommand::new(
             ".prompt",
             "Create a temporary role using a prompt",
         ),
         RealtimeCommand::new(
             ".role",
-            "Create or switch to a specific role",
+            "Switch to a specific role",
+        ),
+        RealtimeCommand::new(
+            ".exit role",
+            "Leave the role",
         ),
         RealtimeCommand::new(
             ".session",
@@ -49,6 +59,10 @@ lazy_static::lazy_static! {
             ".exit rag",
             "Leave the RAG",
         ),
+        RealtimeCommand::new(
+            ".file",
+            "Ask about files, followed by -- and the question",
+        ),
         RealtimeCommand::new(".continue", "Continue the response"),
         RealtimeCommand::new(
             ".regenerate",
@@ -83,6 +97,17 @@ pub fn split_args(args: Option<&str>) -> Option<(&str, Option<&str>)> {
     })
 }
 
+fn dump_table(rows: &[(String, String)]) {
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or_default() + 2;
+    for (name, info) in rows {
+        if info.is_empty() {
+            println!("{name}");
+        } else {
+            println!("{name:<width$}{info}");
+        }
+    }
+}
+
 fn dump_realtime_help() {
     let head = REALTIME_COMMANDS
         .iter()
@@ -4,9 +4,9 @@ use anyhow::{bail, Result};
 use parking_lot::RwLock;
 use reedline::{Reedline, Signal};
 
-use crate::{common::{ask::ask, config::{CConfig, Config}, input::Input, WorkingMode}, realtime::{dump_realtime_help, parse_command, split_args, unknown_command, MULTILINE_RE}};
+use crate::{common::{ask::ask, config::{CConfig, Config}, input::Input, sampling::SAMPLING_KEYS, WorkingMode}, realtime::{dump_realtime_help, dump_table, parse_command, split_args, unknown_command, MULTILINE_RE}};
 
-use super::{abort::{create_abort_signal, AbortSignal}, editor::create_editor, prompt::RealtimePrompt};
+use super::{abort::{create_abort_signal, AbortSignal}, editor::create_editor, prompt::RealtimePrompt, render::TerminalSink};
 
 pub struct Realtime {
     editor: Reedline,
@@ -17,8 +17,8 @@ pub struct Realtime {
 
 impl Realtime {
     pub fn init(config: &str) -> Result<Self> {
-        let editor = create_editor()?;
         let config = Arc::new(RwLock::new(CConfig::init(config.into())?));
+        let editor = create_editor(&config)?;
         let prompt = RealtimePrompt::new(&config);
         let abort_signal = create_abort_signal();
 
@@ -46,7 +46,11 @@ impl Realtime {
             match sig {
                 Ok(Signal::Success(line)) => {
                     self.abort_signal.reset();
-                    match self.handle(&line).await {
+                    let result = self.handle(&line).await;
+                    if let Err(err) = self.editor.sync_history() {
+                        log::warn!("Failed to save the history: {err}");
+                    }
+                    match result {
                         Ok(exit) => {
                             if exit {
                                 break;
@@ -84,33 +88,72 @@ impl Realtime {
                     dump_realtime_help();
                 }
                 ".info" => {
-                    let output = self.config.read().sysinfo()?;
+                    let output = self.config.read().sysinfo(true)?;
                     print!("{}", output);
                 }
+                ".model" => match args {
+                    Some(name) => self.config.write().set_model(name),
+                    None => println!(r#"Usage: .model <model>|<client>:<model>"#),
+                },
+                ".set" => match split_args(args) {
+                    Some((key, Some(value))) => self.config.write().set_sampling(key, value)?,
+                    _ => println!(r#"Usage: .set <key> <value>, keys: {}"#, SAMPLING_KEYS.join(", ")),
+                },
                 ".list" => match args {
-                    Some("session") => {
-                        // let info = self.config.read().session_info()?;
-                        // print!("{}", info);
-                    }
-                    Some("agent") => {
-                        let info = &self.config.read().agents;
-                        print!("{:?}", info);
-                    }
+                    Some("session") => dump_table(&self.config.read().list_sessions_with_autoname()),
+                    Some("rag") => dump_table(&self.config.read().list_rags()),
+                    Some("agent") => dump_table(&self.config.read().list_agents()),
+                    Some("tool") => dump_table(&self.config.read().list_tools()),
+                    Some("role") => dump_table(&self.config.read().list_roles()),
                     Some(_) => unknown_command()?,
-                    None => println!(r#"Usage: .list <session|rag|agent|tool>"#),
+                    None => println!(r#"Usage: .list <session|role|rag|agent|tool>"#),
                 },
                 ".agent" => match split_args(args) {
                     Some((agent_name, session_name)) => {
-                        self.config.write().use_agent(agent_name, session_name).await?;
+                        self.config.write().use_agent(agent_name, session_name)?;
                     }
                     None => println!(r#"Usage: .agent <agent-name> [session-name]"#),
                 },
+                ".role" => match args {
+                    Some(name) => self.config.write().use_role(name)?,
+                    None => println!(r#"Usage: .role <role-name>"#),
+                },
+                ".prompt" => match args {
+                    Some(text) => self.config.write().use_prompt(text)?,
+                    None => println!(r#"Usage: .prompt <text>"#),
+                },
+                ".rag" => match args {
+                    Some(name) => self.config.write().use_rag(name)?,
+                    None => println!(r#"Usage: .rag <rag-name>"#),
+                },
+                ".file" => {
+                    let words: Vec<&str> = args.unwrap_or_default().split_whitespace().collect();
+                    let (paths, text) = match words.iter().position(|v| *v == "--") {
+                        Some(index) => (&words[..index], words[index + 1..].join(" ")),
+                        None => (&words[..], String::new()),
+                    };
+                    if paths.is_empty() {
+                        println!(r#"Usage: .file <path>... [-- <text>]"#);
+                    } else {
+                        let input = Input::from_files(paths, &text)?;
+                        ask(&self.config, input, &TerminalSink::new()).await?;
+                    }
+                }
+                ".continue" => {
+                    let (mut input, output) = match self.config.read().last_message.clone() {
+                        Some(v) => v,
+                        None => bail!("Unable to continue the response"),
+                    };
+                    input.set_continue_output(&output);
+                    ask(&self.config, input, &TerminalSink::new()).await?;
+                }
                 ".regenerate" => {
-                    let (input, _) = match self.config.read().last_message.clone() {
+                    let (mut input, _) = match self.config.read().last_message.clone() {
                         Some(v) => v,
                         None => bail!("Unable to regenerate the last response"),
                     };
-                    ask(&self.config, input).await?;
+                    input.set_regenerate();
+                    ask(&self.config, input, &TerminalSink::new()).await?;
                 }
                 ".session" => {
                     self.config.write().use_session(args)?;
@@ -120,22 +163,40 @@ impl Realtime {
                         self.config.write().empty_session()?;
                     }
                     _ => {
-                        println!(r#"Usage: .empty <session> [name]"#)
+                        println!(r#"Usage: .empty session"#)
+                    }
+                },
+                ".save" => match split_args(args) {
+                    Some(("session", name)) => {
+                        self.config.write().save_session(name)?;
+                    }
+                    _ => {
+                        println!(r#"Usage: .save session [name]"#)
                     }
                 },
                 ".exit" => match args {
                     Some("session") => {
                         self.config.write().exit_session()?;
                     }
-                    _ => {
-                        println!(r#"Usage: .exit <session> [name]"#)
+                    Some("agent") => {
+                        self.config.write().exit_agent()?;
+                    }
+                    Some("rag") => {
+                        self.config.write().exit_rag()?;
+                    }
+                    Some("role") => {
+                        self.config.write().exit_role()?;
+                    }
+                    Some(_) => {
+                        println!(r#"Usage: .exit [session|agent|role|rag]"#)
                     }
+                    None => return Ok(true),
                 },
                 _ => unknown_command()?,
             },
             None => {
                 let input = Input::from_str( line);
-                ask(&self.config, input).await?;
+                ask(&self.config, input, &TerminalSink::new()).await?;
             }
         }
         println!();
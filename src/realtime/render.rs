@@ -0,0 +1,36 @@
+use std::io::{stdout, Write};
+
+use anyhow::Result;
+
+use crate::common::event::{AskEvent, EventSink};
+
+/// Renders `ask` events on the terminal, errors are left to the Realtime loop
+#[derive(Default)]
+pub struct TerminalSink;
+
+impl TerminalSink {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl EventSink for TerminalSink {
+    fn emit(&self, event: AskEvent) -> Result<()> {
+        let mut lock = stdout().lock();
+        match event {
+            AskEvent::TextDelta(text) => write!(lock, "{}", text)?,
+            AskEvent::ToolCallStarted { name, arguments, .. } => {
+                writeln!(lock, "\u{1b}[90mCall {name} {arguments}\u{1b}[0m")?
+            }
+            AskEvent::Sources(sources) => {
+                write!(lock, "\n\n\u{1b}[90mSources: {}\u{1b}[0m", sources.join(", "))?
+            }
+            AskEvent::ToolCallFinished { .. }
+            | AskEvent::Usage(_)
+            | AskEvent::Error(_)
+            | AskEvent::Done => {}
+        }
+        lock.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, middleware::Next, web::Data, Error, HttpMessage, HttpRequest};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::common::error::RagentsError;
+
+use super::{response::error_response, state::ClientStore};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Client keys accepted as `Authorization: Bearer <key>`, the server is open when empty
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiKey {
+    pub name: Option<String>,
+    /// The key in plain text
+    pub key: Option<String>,
+    /// Hex encoded SHA-256 of the key, used instead of `key`
+    pub key_sha256: Option<String>,
+    /// Agents the key may use, all when empty
+    pub agents: Vec<String>,
+    /// Upstream models the key may use, all when empty
+    pub models: Vec<String>,
+}
+
+impl ApiKey {
+    pub fn matches(&self, token: &str) -> bool {
+        if let Some(key) = &self.key {
+            if constant_time_eq(key.as_bytes(), token.as_bytes()) {
+                return true;
+            }
+        }
+        if let Some(key_sha256) = &self.key_sha256 {
+            let digest = format!("{:x}", Sha256::digest(token.as_bytes()));
+            if constant_time_eq(key_sha256.to_ascii_lowercase().as_bytes(), digest.as_bytes()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Tells keys apart without holding the secret, the hex SHA-256 of the key
+    pub fn id(&self) -> String {
+        match (&self.key_sha256, &self.key) {
+            (Some(key_sha256), _) => key_sha256.to_ascii_lowercase(),
+            (None, Some(key)) => format!("{:x}", Sha256::digest(key.as_bytes())),
+            (None, None) => String::new(),
+        }
+    }
+
+    pub fn allow_agent(&self, name: &str) -> bool {
+        self.agents.is_empty() || self.agents.iter().any(|v| v == name)
+    }
+
+    pub fn allow_model(&self, name: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|v| v == name)
+    }
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn find(&self, token: &str) -> Option<&ApiKey> {
+        self.keys.iter().find(|key| key.matches(token))
+    }
+}
+
+/// The key that authenticated the request, if auth is enabled
+pub fn request_key(req: &HttpRequest) -> Option<ApiKey> {
+    req.extensions().get::<ApiKey>().cloned()
+}
+
+pub async fn auth_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let auth = match req.app_data::<Data<ClientStore>>() {
+        Some(store) => store.base().read().auth.clone(),
+        None => AuthConfig::default(),
+    };
+    if !auth.is_enabled() {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+    let key = token.as_deref().and_then(|token| auth.find(token)).cloned();
+    match key {
+        Some(key) => {
+            req.extensions_mut().insert(key);
+            next.call(req).await.map(|res| res.map_into_boxed_body())
+        }
+        None => {
+            let message = match token {
+                Some(_) => "Incorrect API key provided.",
+                None => "You didn't provide an API key. Provide it in the Authorization header using Bearer auth.",
+            };
+            let response = error_response(RagentsError::InvalidApiKey(message.to_string()));
+            Ok(req.into_response(response))
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
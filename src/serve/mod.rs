@@ -1,2 +1,5 @@
 pub mod server;
 pub mod route;
+pub mod response;
+pub mod state;
+pub mod auth;
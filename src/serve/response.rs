@@ -0,0 +1,201 @@
+use actix_web::{http::StatusCode, web::Bytes, HttpResponse};
+use anyhow::Result;
+use parking_lot::Mutex;
+use async_openai::types::{ChatChoice, ChatChoiceStream, ChatCompletionResponseMessage, ChatCompletionStreamResponseDelta, CompletionUsage, CreateChatCompletionResponse, CreateChatCompletionStreamResponse, FinishReason, Role};
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::common::{error::ErrorResponse, event::{AskEvent, EventSink}};
+
+pub const SSE_DONE: &str = "data: [DONE]\n\n";
+
+pub fn completion_id() -> String {
+    format!("chatcmpl-{}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default())
+}
+
+pub fn created() -> u32 {
+    chrono::Utc::now().timestamp() as u32
+}
+
+pub fn sse_frame<T: Serialize>(value: &T) -> Bytes {
+    let data = serde_json::to_string(value).unwrap_or_default();
+    Bytes::from(format!("data: {data}\n\n"))
+}
+
+pub fn chat_completion_chunk(
+    id: &str,
+    model: &str,
+    created: u32,
+    role: Option<Role>,
+    content: Option<String>,
+    finish_reason: Option<FinishReason>,
+) -> CreateChatCompletionStreamResponse {
+    #[allow(deprecated)]
+    let delta = ChatCompletionStreamResponseDelta {
+        content,
+        function_call: None,
+        tool_calls: None,
+        role,
+        refusal: None,
+    };
+    CreateChatCompletionStreamResponse {
+        id: id.to_string(),
+        choices: vec![ChatChoiceStream {
+            index: 0,
+            delta,
+            finish_reason,
+            logprobs: None,
+        }],
+        created,
+        model: model.to_string(),
+        service_tier: None,
+        system_fingerprint: None,
+        object: "chat.completion.chunk".to_string(),
+        usage: None,
+    }
+}
+
+pub fn chat_completion_response(id: &str, model: &str, content: String, usage: Option<CompletionUsage>) -> CreateChatCompletionResponse {
+    #[allow(deprecated)]
+    let message = ChatCompletionResponseMessage {
+        content: Some(content),
+        refusal: None,
+        tool_calls: None,
+        role: Role::Assistant,
+        function_call: None,
+        audio: None,
+    };
+    CreateChatCompletionResponse {
+        id: id.to_string(),
+        choices: vec![ChatChoice {
+            index: 0,
+            message,
+            finish_reason: Some(FinishReason::Stop),
+            logprobs: None,
+        }],
+        created: created(),
+        model: model.to_string(),
+        service_tier: None,
+        system_fingerprint: None,
+        object: "chat.completion".to_string(),
+        usage,
+    }
+}
+
+/// Answer with the OpenAI error body and the status matching the kind of `err`
+pub fn error_response(err: impl Into<ErrorResponse>) -> HttpResponse {
+    let err = err.into();
+    let status = StatusCode::from_u16(err.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    if status.is_server_error() {
+        log::error!("{}", err.detail);
+    }
+    HttpResponse::build(status).json(err.to_json())
+}
+
+/// Renders `ask` events as `chat.completion.chunk` server-sent events
+pub struct SseSink {
+    sender: UnboundedSender<Bytes>,
+    id: String,
+    model: String,
+    created: u32,
+    /// Whether the request set `stream_options.include_usage`
+    include_usage: bool,
+    /// Sent after the last choice, as OpenAI does
+    usage: Mutex<Option<CompletionUsage>>,
+}
+
+impl SseSink {
+    pub fn new(sender: UnboundedSender<Bytes>, model: &str, include_usage: bool) -> Self {
+        let sink = Self {
+            sender,
+            id: completion_id(),
+            model: model.to_string(),
+            created: created(),
+            include_usage,
+            usage: Mutex::new(None),
+        };
+        sink.send_chunk(Some(Role::Assistant), Some(String::new()), None);
+        sink
+    }
+
+    fn send(&self, frame: Bytes) {
+        let _ = self.sender.send(frame);
+    }
+
+    fn send_chunk(&self, role: Option<Role>, content: Option<String>, finish_reason: Option<FinishReason>) {
+        let chunk = chat_completion_chunk(&self.id, &self.model, self.created, role, content, finish_reason);
+        self.send(sse_frame(&chunk));
+    }
+}
+
+impl EventSink for SseSink {
+    fn emit(&self, event: AskEvent) -> Result<()> {
+        match event {
+            AskEvent::TextDelta(text) => self.send_chunk(None, Some(text), None),
+            AskEvent::Usage(usage) => {
+                if self.include_usage {
+                    *self.usage.lock() = Some(usage);
+                }
+            }
+            AskEvent::Error(err) => {
+                self.send(sse_frame(&err.to_json()));
+                self.send(Bytes::from(SSE_DONE));
+            }
+            AskEvent::Done => {
+                self.send_chunk(None, None, Some(FinishReason::Stop));
+                if let Some(usage) = self.usage.lock().take() {
+                    let mut chunk = chat_completion_chunk(&self.id, &self.model, self.created, None, None, None);
+                    chunk.choices.clear();
+                    chunk.usage = Some(usage);
+                    self.send(sse_frame(&chunk));
+                }
+                self.send(Bytes::from(SSE_DONE));
+            }
+            AskEvent::ToolCallStarted { .. }
+            | AskEvent::ToolCallFinished { .. }
+            | AskEvent::Sources(_) => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn sse_frames(include_usage: bool) -> Vec<String> {
+        let (tx, mut rx) = unbounded_channel();
+        let sink = SseSink::new(tx, "mock", include_usage);
+        let usage = CompletionUsage {
+            prompt_tokens: 10,
+            completion_tokens: 2,
+            total_tokens: 12,
+            prompt_tokens_details: None,
+            completion_tokens_details: None,
+        };
+        sink.emit(AskEvent::TextDelta("Hi".into())).unwrap();
+        sink.emit(AskEvent::Usage(usage)).unwrap();
+        sink.emit(AskEvent::Done).unwrap();
+        drop(sink);
+        let mut frames = vec![];
+        while let Ok(frame) = rx.try_recv() {
+            frames.push(String::from_utf8(frame.to_vec()).unwrap());
+        }
+        frames
+    }
+
+    #[test]
+    fn test_sse_usage() {
+        let frames = sse_frames(false);
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(|frame| !frame.contains("\"total_tokens\"")));
+
+        let frames = sse_frames(true);
+        assert_eq!(frames.len(), 5);
+        assert!(frames[2].contains("\"finish_reason\":\"stop\""));
+        assert!(frames[3].contains("\"choices\":[]") && frames[3].contains("\"total_tokens\":12"));
+        assert_eq!(frames[4], SSE_DONE);
+    }
+}
@@ -1,47 +1,192 @@
-use actix_web::{web::{self}, HttpResponse, Responder};
-use async_openai::types::CreateChatCompletionRequest;
+use std::sync::Arc;
+
+use actix_web::{error::InternalError, middleware::from_fn, web::{self, Bytes}, HttpRequest, HttpResponse, Responder};
+use async_openai::types::{CreateChatCompletionRequest, ListModelResponse, Model};
+use futures::stream;
+use parking_lot::RwLock;
 use serde::Deserialize;
+use tokio::sync::mpsc::unbounded_channel;
+
+use serde_json::json;
+
+use super::{auth::{auth_middleware, request_key}, response::{chat_completion_response, completion_id, error_response, SseSink}, state::{attach_client_id, client_id, ClientStore}};
+
+use crate::common::{ask::ask, config::Config, error::RagentsError, event::CollectSink, input::Input, session::Session, AGENT_MODEL_PREFIX, ALLOW_TOOLS_HEADER, CLIENT_HEADER, DEFAULT_CLIENT_NAME, TEMP_SESSION_NAME};
+pub async fn chat_completions(req: HttpRequest, a:web::Json<CreateChatCompletionRequest>, store:web::Data<ClientStore>) -> impl Responder {
+    let agent_name = a.model.strip_prefix(AGENT_MODEL_PREFIX).map(|v| v.to_string());
+    chat_completions_with_agent(req, a.0, &store, agent_name.as_deref()).await
+}
+
+pub async fn agent_chat_completions(req: HttpRequest, path: web::Path<String>, a:web::Json<CreateChatCompletionRequest>, store:web::Data<ClientStore>) -> impl Responder {
+    chat_completions_with_agent(req, a.0, &store, Some(&path.into_inner())).await
+}
 
-use crate::common::{ask::ask, config::Config, input::Input};
-pub async fn chat_completions(a:web::Json<CreateChatCompletionRequest>, config:web::Data<Config>) -> impl Responder {
-    let input = Input::from_web(&a.0);
-    match ask(&config, input).await {
+async fn chat_completions_with_agent(req: HttpRequest, request: CreateChatCompletionRequest, store: &ClientStore, agent_name: Option<&str>) -> HttpResponse {
+    if let Some(key) = request_key(&req) {
+        let allowed = match agent_name {
+            Some(agent_name) => key.allow_agent(agent_name),
+            None => key.allow_model(&request.model),
+        };
+        if !allowed {
+            return error_response(RagentsError::ModelNotAllowed(request.model.clone()));
+        }
+    }
+    let (id, config) = store.get_or_create(client_id(&req).as_deref(), key_id(&req).as_deref());
+    let response = chat_completions_with_config(&req, request, config, agent_name).await;
+    attach_client_id(response, &id)
+}
+
+async fn chat_completions_with_config(req: &HttpRequest, mut request: CreateChatCompletionRequest, config: Config, agent_name: Option<&str>) -> HttpResponse {
+    let config = match agent_name {
+        Some(agent_name) => match config.read().fork_with_agent(agent_name) {
+            Ok(v) => Arc::new(RwLock::new(v)),
+            Err(e) => return error_response(e),
+        },
+        None => config,
+    };
+    let model = request.model.clone();
+    if agent_name.is_some() {
+        request.model = config.read().current_model();
+    }
+    let mut input = Input::from_web(&request);
+    input.set_allow_tools(allow_tools(req));
+    if request.stream.unwrap_or(false) {
+        let include_usage = request.stream_options.as_ref().is_some_and(|v| v.include_usage);
+        return chat_completions_streaming(config, input, model, include_usage);
+    }
+    let sink = CollectSink::new();
+    match ask(&config, input, &sink).await {
         Ok(response) => {
-            HttpResponse::Ok().json(response)
+            HttpResponse::Ok().json(chat_completion_response(&completion_id(), &model, response, sink.usage()))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+fn chat_completions_streaming(config: Config, input: Input, model: String, include_usage: bool) -> HttpResponse {
+    let (tx, rx) = unbounded_channel::<Bytes>();
+    let sink = SseSink::new(tx, &model, include_usage);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = ask(&config, input, &sink).await {
+            log::error!("{e:#}");
+        }
+    });
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        let frame = rx.recv().await?;
+        Some((Ok::<_, actix_web::Error>(frame), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
+
+/// Id of the API key of the request, clients are bound to it
+fn key_id(req: &HttpRequest) -> Option<String> {
+    request_key(req).map(|key| key.id())
+}
+
+/// Whether the API key may use the agent, or else the model, that `config` answers with
+fn check_allowed(req: &HttpRequest, config: &Config) -> Result<(), RagentsError> {
+    let key = match request_key(req) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let config = config.read();
+    let (name, allowed) = match &config.agent {
+        Some(agent) => (format!("{AGENT_MODEL_PREFIX}{}", agent.name), key.allow_agent(&agent.name)),
+        None => {
+            let model = config.current_model();
+            let allowed = key.allow_model(&model);
+            (model, allowed)
         }
-        Err(e) => {
-            println!("Error occurred: {:?}", e); 
-            HttpResponse::InternalServerError().body("error")
+    };
+    if !allowed {
+        return Err(RagentsError::ModelNotAllowed(name));
+    }
+    Ok(())
+}
+
+fn allow_tools(req: &HttpRequest) -> Vec<String> {
+    req.headers()
+        .get(ALLOW_TOOLS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+pub async fn models(req: HttpRequest, store:web::Data<ClientStore>) -> impl Responder {
+    let config = store.base();
+    let llm_clients = config.read().llm_clients.clone();
+    let mut data = vec![];
+    for llm_client in llm_clients {
+        match llm_client.client.models().list().await {
+            Ok(response) => data.extend(response.data.into_iter().map(|mut model| {
+                if llm_client.name != DEFAULT_CLIENT_NAME {
+                    model.id = format!("{}:{}", llm_client.name, model.id);
+                }
+                model
+            })),
+            Err(e) => log::warn!("Failed to list models of client '{}': {e}", llm_client.name),
         }
     }
+    let mut agents: Vec<&String> = vec![];
+    let config = config.read();
+    agents.extend(config.agents.keys());
+    agents.sort();
+    data.extend(agents.into_iter().map(|name| Model {
+        id: format!("{AGENT_MODEL_PREFIX}{name}"),
+        object: "model".to_string(),
+        created: 0,
+        owned_by: "r-agents".to_string(),
+    }));
+    if let Some(key) = request_key(&req) {
+        data.retain(|model| match model.id.strip_prefix(AGENT_MODEL_PREFIX) {
+            Some(agent_name) => key.allow_agent(agent_name),
+            None => key.allow_model(&model.id),
+        });
+    }
+    HttpResponse::Ok().json(ListModelResponse {
+        object: "list".to_string(),
+        data,
+    })
 }
 
-pub async fn info(config:web::Data<Config>) -> impl Responder {
-    let info = config.read().sysinfo();
+pub async fn info(req: HttpRequest, store:web::Data<ClientStore>) -> impl Responder {
+    let config = client_id(&req)
+        .and_then(|id| store.get(&id, key_id(&req).as_deref()))
+        .unwrap_or_else(|| store.base().clone());
+    let info = config.read().sysinfo(false);
     match info {
         Ok(response) => {
             HttpResponse::Ok().body(response)
         }
-        Err(e) => {
-            println!("Error occurred: {:?}", e); 
-            HttpResponse::InternalServerError().body("error")
-        }
+        Err(e) => error_response(e),
     }
 }
 
-pub async fn regenerate(config:web::Data<Config>) -> impl Responder {
-    let (input, _) = match config.read().last_message.clone() {
+pub async fn regenerate(req: HttpRequest, store:web::Data<ClientStore>) -> impl Responder {
+    let config = match client_id(&req).and_then(|id| store.get(&id, key_id(&req).as_deref())) {
+        Some(v) => v,
+        None => return error_response(RagentsError::ClientNotFound(format!("Unknown client, send the '{CLIENT_HEADER}' header"))),
+    };
+    if let Err(e) = check_allowed(&req, &config) {
+        return error_response(e);
+    }
+    let (mut input, _) = match config.read().last_message.clone() {
         Some(v) => v,
-        None => return HttpResponse::InternalServerError().body("error"),
+        None => return error_response(RagentsError::InvalidRequest("No message to regenerate".into())),
     };
-    match ask(&config, input).await {
+    input.set_regenerate();
+    let model = config.read().current_model();
+    let sink = CollectSink::new();
+    match ask(&config, input, &sink).await {
         Ok(response) => {
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            println!("Error occurred: {:?}", e); 
-            HttpResponse::InternalServerError().body("error")
+            HttpResponse::Ok().json(chat_completion_response(&completion_id(), &model, response, sink.usage()))
         }
+        Err(e) => error_response(e),
     }
 }
 
@@ -50,26 +195,188 @@ pub struct SessionWebParams {
     pub name: String,
 }
 
-pub async fn session(param:web::Query<SessionWebParams>, config:web::Data<Config>) -> impl Responder {
+pub async fn session(req: HttpRequest, param:web::Query<SessionWebParams>, store:web::Data<ClientStore>) -> impl Responder {
     let name = &param.0.name;
-    match config.write().use_session(Some(&name)) {
-        Ok(response) => {
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            println!("Error occurred: {:?}", e); 
-            HttpResponse::InternalServerError().body("error")
+    if let Err(e) = check_session_name(name) {
+        return error_response(e);
+    }
+    let (id, config) = store.get_or_create(client_id(&req).as_deref(), key_id(&req).as_deref());
+    let result = config.write().use_session(Some(name));
+    let response = match result {
+        Ok(_) => {
+            HttpResponse::Ok().json(json!({ "client": id, "session": name }))
         }
+        Err(e) => error_response(e),
+    };
+    attach_client_id(response, &id)
+}
+
+#[derive(Debug,Deserialize,Default)]
+pub struct SessionNameParams {
+    pub name: String,
+}
+
+#[derive(Debug,Deserialize,Default)]
+pub struct SessionMessageParams {
+    pub content: String,
+}
+
+fn check_session_name(name: &str) -> Result<(), RagentsError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('/')
+        && name.split('/').all(|part| !part.is_empty() && part != "." && part != "..");
+    if !valid {
+        return Err(RagentsError::InvalidRequest(format!("Invalid session name '{name}'")));
+    }
+    if name == TEMP_SESSION_NAME {
+        return Err(RagentsError::InvalidRequest(format!("Session name '{name}' is reserved")));
+    }
+    Ok(())
+}
+
+pub async fn list_sessions(store:web::Data<ClientStore>) -> impl Responder {
+    let sessions = store.base().read().list_sessions();
+    HttpResponse::Ok().json(json!({ "sessions": sessions }))
+}
+
+pub async fn create_session(params:web::Json<SessionNameParams>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = &params.name;
+    if let Err(e) = check_session_name(name) {
+        return error_response(e);
+    }
+    let config = store.base().read();
+    let session_path = config.session_file(name);
+    if session_path.exists() {
+        return error_response(RagentsError::SessionExists(name.to_string()));
+    }
+    match Session::new(name).save(name, &session_path, false) {
+        Ok(_) => HttpResponse::Created().json(json!({ "name": name, "messages": [] })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn get_session(path:web::Path<String>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    if let Err(e) = check_session_name(&name) {
+        return error_response(e);
+    }
+    match store.base().read().load_session(&name) {
+        Ok(session) => HttpResponse::Ok().json(json!({ "name": name, "messages": session.messages() })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn rename_session(path:web::Path<String>, params:web::Json<SessionNameParams>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    let new_name = &params.name;
+    if let Err(e) = check_session_name(&name).and_then(|_| check_session_name(new_name)) {
+        return error_response(e);
+    }
+    match store.base().read().rename_session(&name, new_name) {
+        Ok(_) => HttpResponse::Ok().json(json!({ "name": new_name })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn delete_session(path:web::Path<String>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    if let Err(e) = check_session_name(&name) {
+        return error_response(e);
+    }
+    match store.base().read().delete_session(&name) {
+        Ok(_) => HttpResponse::Ok().json(json!({ "name": name, "deleted": true })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn clear_session(path:web::Path<String>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    if let Err(e) = check_session_name(&name) {
+        return error_response(e);
+    }
+    if let Err(e) = store.base().read().load_session(&name) {
+        return error_response(e);
+    }
+    let config = store.fork();
+    let result = {
+        let mut config = config.write();
+        config.use_session(Some(&name))
+            .and_then(|_| config.empty_session())
+            .and_then(|_| config.save_session(None))
+    };
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({ "name": name, "messages": [] })),
+        Err(e) => error_response(e),
     }
 }
 
+pub async fn save_session(req: HttpRequest, path:web::Path<String>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    if let Err(e) = check_session_name(&name) {
+        return error_response(e);
+    }
+    let config = match client_id(&req).and_then(|id| store.get(&id, key_id(&req).as_deref())) {
+        Some(v) => v,
+        None => return error_response(RagentsError::ClientNotFound("No client session to save".into())),
+    };
+    let result = config.write().save_session(Some(&name));
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({ "name": name })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub async fn post_session_message(req: HttpRequest, path:web::Path<String>, params:web::Json<SessionMessageParams>, store:web::Data<ClientStore>) -> impl Responder {
+    let name = path.into_inner();
+    if let Err(e) = check_session_name(&name) {
+        return error_response(e);
+    }
+    if let Err(e) = store.base().read().load_session(&name) {
+        return error_response(e);
+    }
+    let config = store.fork();
+    let result = config.write().use_session(Some(&name));
+    if let Err(e) = result {
+        return error_response(e);
+    }
+    if let Err(e) = check_allowed(&req, &config) {
+        return error_response(e);
+    }
+    let model = config.read().current_model();
+    let sink = CollectSink::new();
+    let response = match ask(&config, Input::from_str(&params.content), &sink).await {
+        Ok(v) => v,
+        Err(e) => return error_response(e),
+    };
+    let result = config.write().save_session(None);
+    match result {
+        Ok(_) => HttpResponse::Ok().json(chat_completion_response(&completion_id(), &model, response, sink.usage())),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Reports a body, path or query that failed to parse in the OpenAI error shape
+fn invalid_request<E: std::fmt::Debug + std::fmt::Display + 'static>(err: E, _req: &HttpRequest) -> actix_web::Error {
+    let response = error_response(RagentsError::InvalidRequest(err.to_string()));
+    InternalError::from_response(err, response).into()
+}
 
 pub fn app_config(config:&mut web::ServiceConfig){
     config.service(
         web::scope("/r-agents")
+            .app_data(web::JsonConfig::default().error_handler(invalid_request))
+            .app_data(web::PathConfig::default().error_handler(invalid_request))
+            .app_data(web::QueryConfig::default().error_handler(invalid_request))
+            .wrap(from_fn(auth_middleware))
             .service(web::resource("/v1/chat/completions")
                 .route( web::post().to(chat_completions))
             )
+            .service(web::resource("/v1/agents/{name}/chat/completions")
+                .route( web::post().to(agent_chat_completions))
+            )
+            .service(web::resource("/v1/models")
+                .route( web::get().to(models))
+            )
             .service(web::resource("/info")
                 .route( web::post().to(info))
             )
@@ -79,5 +386,24 @@ pub fn app_config(config:&mut web::ServiceConfig){
             .service(web::resource("/session")
                 .route( web::get().to(session))
             )
+            .service(web::resource("/v1/sessions")
+                .route( web::get().to(list_sessions))
+                .route( web::post().to(create_session))
+            )
+            .service(web::resource("/v1/sessions/{name:.+}/clear")
+                .route( web::post().to(clear_session))
+            )
+            .service(web::resource("/v1/sessions/{name:.+}/save")
+                .route( web::post().to(save_session))
+            )
+            .service(web::resource("/v1/sessions/{name:.+}/messages")
+                .route( web::get().to(get_session))
+                .route( web::post().to(post_session_message))
+            )
+            .service(web::resource("/v1/sessions/{name:.+}")
+                .route( web::get().to(get_session))
+                .route( web::patch().to(rename_session))
+                .route( web::delete().to(delete_session))
+            )
     );
 }
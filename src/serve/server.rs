@@ -4,7 +4,7 @@ use std::sync::Arc;
 use actix_web::{middleware, web::Data, App, HttpServer};
 use anyhow::Result;
 use parking_lot::RwLock;
-use crate::{common::{config::{CConfig, Config}, WorkingMode}, serve::route::app_config};
+use crate::{common::{config::{CConfig, Config}, WorkingMode}, serve::{route::app_config, state::ClientStore}};
 
 #[derive(Clone)]
 pub struct Server {
@@ -28,12 +28,11 @@ impl Server {
     pub async fn run(&mut self) -> Result<()>{
         self.boot()?;
         let http_addr = self.config.read().get_http_addr()?;
-        let config = self.config.clone();
+        let store = Data::new(ClientStore::new(&self.config));
         log::info!("http server addr:{}",&http_addr);
         let server = HttpServer::new(move || {
-            let config = config.clone();
             App::new()
-                .app_data(Data::new(config))
+                .app_data(store.clone())
                 .wrap(middleware::Logger::default())
                 .configure(app_config)
         });
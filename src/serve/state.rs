@@ -0,0 +1,142 @@
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+
+use actix_web::{cookie::Cookie, HttpRequest, HttpResponse};
+use parking_lot::RwLock;
+use rand::{rngs::OsRng, RngCore};
+
+use crate::common::{config::Config, CLIENT_COOKIE, CLIENT_HEADER};
+
+/// Length of the hex ids handed out by `new_client_id`
+const CLIENT_ID_LEN: usize = 32;
+
+struct ClientState {
+    config: Config,
+    /// Id of the API key that created the client, `None` when auth is off
+    owner: Option<String>,
+    last_seen: Instant,
+}
+
+/// Conversation state of every client, keyed by the id sent in `CLIENT_HEADER` or `CLIENT_COOKIE`
+pub struct ClientStore {
+    base: Config,
+    clients: RwLock<HashMap<String, ClientState>>,
+    ttl: Duration,
+}
+
+impl ClientStore {
+    pub fn new(base: &Config) -> Self {
+        let ttl = Duration::from_secs(base.read().client_ttl());
+        Self {
+            base: base.clone(),
+            clients: Default::default(),
+            ttl,
+        }
+    }
+
+    pub fn base(&self) -> &Config {
+        &self.base
+    }
+
+    /// The config of an existing client, if it was created with the same API key
+    pub fn get(&self, id: &str, owner: Option<&str>) -> Option<Config> {
+        self.evict_expired();
+        let mut clients = self.clients.write();
+        let state = clients.get_mut(id).filter(|state| state.owner.as_deref() == owner)?;
+        state.last_seen = Instant::now();
+        Some(state.config.clone())
+    }
+
+    /// The id and config of the client, a new client with a fresh id when `id` is unknown
+    pub fn get_or_create(&self, id: Option<&str>, owner: Option<&str>) -> (String, Config) {
+        if let Some((id, config)) = id.and_then(|id| Some((id, self.get(id, owner)?))) {
+            return (id.to_string(), config);
+        }
+        let id = new_client_id();
+        let config = self.fork();
+        self.clients.write().insert(
+            id.clone(),
+            ClientState {
+                config: config.clone(),
+                owner: owner.map(|v| v.to_string()),
+                last_seen: Instant::now(),
+            },
+        );
+        (id, config)
+    }
+
+    /// A config that is not shared with any other request
+    pub fn fork(&self) -> Config {
+        let mut config = self.base.read().clone();
+        config.session = None;
+        config.last_message = None;
+        Arc::new(RwLock::new(config))
+    }
+
+    fn evict_expired(&self) {
+        let now = Instant::now();
+        let expired: Vec<ClientState> = {
+            let mut clients = self.clients.write();
+            let ids: Vec<String> = clients
+                .iter()
+                .filter(|(_, state)| now.duration_since(state.last_seen) > self.ttl)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| clients.remove(id)).collect()
+        };
+        for state in expired {
+            if let Err(err) = state.config.write().exit_session() {
+                log::warn!("Failed to exit expired session: {err:#}");
+            }
+        }
+    }
+}
+
+/// The client id sent with the request, values that can't have been issued by `new_client_id` are dropped
+pub fn client_id(req: &HttpRequest) -> Option<String> {
+    let id = match req.headers().get(CLIENT_HEADER).and_then(|v| v.to_str().ok()) {
+        Some(id) if !id.is_empty() => id.to_string(),
+        _ => req.cookie(CLIENT_COOKIE)?.value().to_string(),
+    };
+    let valid = id.len() == CLIENT_ID_LEN && id.bytes().all(|b| b.is_ascii_hexdigit());
+    valid.then_some(id)
+}
+
+fn new_client_id() -> String {
+    let mut bytes = [0u8; CLIENT_ID_LEN / 2];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hand the client id back so later requests reach the same state
+pub fn attach_client_id(mut response: HttpResponse, id: &str) -> HttpResponse {
+    if let Ok(value) = id.parse() {
+        response.headers_mut().insert(CLIENT_HEADER.parse().unwrap(), value);
+    }
+    let cookie = Cookie::build(CLIENT_COOKIE, id.to_string()).path("/").http_only(true).finish();
+    let _ = response.add_cookie(&cookie);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::CConfig;
+
+    #[test]
+    fn test_get_or_create() {
+        let config: CConfig = serde_yaml::from_str("model: mock\nagents: {}\ntools: {}\nrags: {}\n").unwrap();
+        let store = ClientStore::new(&Arc::new(RwLock::new(config)));
+
+        let (id, config) = store.get_or_create(None, Some("alice"));
+        assert_eq!(id.len(), CLIENT_ID_LEN);
+        let (same, same_config) = store.get_or_create(Some(&id), Some("alice"));
+        assert_eq!(same, id);
+        assert!(Arc::ptr_eq(&config, &same_config));
+        assert!(store.get(&id, Some("bob")).is_none());
+        assert!(store.get(&id, None).is_none());
+
+        let (fresh, _) = store.get_or_create(Some("chosen-by-client"), None);
+        assert_ne!(fresh, "chosen-by-client");
+        assert!(store.get("chosen-by-client", None).is_none());
+    }
+}